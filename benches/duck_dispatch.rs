@@ -0,0 +1,69 @@
+//! 对比静态分发的 GenericDuck 和 trait object 的 DynamicDuck 在热循环里 quack 一百万次的耗时
+//! 运行：cargo bench --bench duck_dispatch
+
+use head_first_design_patterns::strategy::{
+    Duck, DynamicDuck, FlyNoWay, GenericDuck, QuackBehavior,
+};
+use std::cell::Cell;
+use std::hint::black_box;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const QUACKS: usize = 1_000_000;
+const ROUNDS: usize = 10;
+
+/// 只计数不输出，这样测到的是分发的开销而不是输出的开销
+struct CountingQuack {
+    count: Rc<Cell<usize>>,
+}
+impl QuackBehavior for CountingQuack {
+    fn quack(&self) {
+        self.count.set(black_box(self.count.get() + 1));
+    }
+}
+
+fn best_of<F: FnMut()>(mut f: F) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let generic_count = Rc::new(Cell::new(0));
+    let generic = GenericDuck::new(
+        FlyNoWay,
+        CountingQuack {
+            count: generic_count.clone(),
+        },
+    );
+    let generic_time = best_of(|| {
+        for _ in 0..QUACKS {
+            black_box(&generic).perform_quack();
+        }
+    });
+
+    let dynamic_count = Rc::new(Cell::new(0));
+    let dynamic: DynamicDuck = GenericDuck::new(
+        FlyNoWay,
+        CountingQuack {
+            count: dynamic_count.clone(),
+        },
+    )
+    .into();
+    let dynamic_time = best_of(|| {
+        for _ in 0..QUACKS {
+            black_box(&dynamic).perform_quack();
+        }
+    });
+
+    assert_eq!(generic_count.get(), QUACKS * ROUNDS);
+    assert_eq!(dynamic_count.get(), QUACKS * ROUNDS);
+    println!("{} quacks, best of {} rounds", QUACKS, ROUNDS);
+    println!("GenericDuck: {:?}", generic_time);
+    println!("DynamicDuck: {:?}", dynamic_time);
+}
@@ -10,10 +10,10 @@ pub trait Duck {
 struct MallardDuck;
 impl Duck for MallardDuck {
     fn quack(&self) {
-        println!("Quack");
+        outputln!("Quack");
     }
     fn fly(&self) {
-        println!("I'm flying");
+        outputln!("I'm flying");
     }
 }
 
@@ -24,10 +24,10 @@ pub trait Turkey {
 struct WildTurkey;
 impl Turkey for WildTurkey {
     fn gobble(&self) {
-        println!("Gobble gobble");
+        outputln!("Gobble gobble");
     }
     fn fly(&self) {
-        println!("I'm flying a short distance");
+        outputln!("I'm flying a short distance");
     }
 }
 
@@ -53,7 +53,7 @@ fn teset_turkey_adapter() {
         turkey: Box::new(WildTurkey {}),
     };
 
-    println!("The Turkey says...");
+    outputln!("The Turkey says...");
     turkey.gobble();
     turkey.fly();
 
@@ -62,9 +62,9 @@ fn teset_turkey_adapter() {
         duck.fly();
     }
 
-    println!("\nThe Duck says...");
+    outputln!("\nThe Duck says...");
     test_duct(Box::new(duck));
 
-    println!("\nThe TurkeyAdapter says...");
+    outputln!("\nThe TurkeyAdapter says...");
     test_duct(Box::new(turkey_adapter));
 }
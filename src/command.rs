@@ -30,10 +30,10 @@ impl Light {
         }
     }
     fn on(&self) {
-        println!("{} Light is on", self.name);
+        outputln!("{} Light is on", self.name);
     }
     fn off(&self) {
-        println!("{} Light is off", self.name);
+        outputln!("{} Light is off", self.name);
     }
 }
 
@@ -54,17 +54,17 @@ impl Stereo {
         }
     }
     fn on(&self) {
-        println!("{} Stereo is on", self.name);
+        outputln!("{} Stereo is on", self.name);
     }
     fn off(&self) {
-        println!("{} Stereo is off", self.name);
+        outputln!("{} Stereo is off", self.name);
     }
     fn set_cd(&mut self, cd: Cd) {
-        println!("{} Stereo is set for Cd input", self.name);
+        outputln!("{} Stereo is set for Cd input", self.name);
         self.cd = Some(cd);
     }
     fn set_volumn(&mut self, volume: usize) {
-        println!("{} Stereo volume set to {}", self.name, volume);
+        outputln!("{} Stereo volume set to {}", self.name, volume);
         self.volume = volume;
     }
 }
@@ -316,11 +316,11 @@ fn test_command_undo() {
 
     remote_control.on_button_was_pushed(0);
     remote_control.off_button_was_pushed(0);
-    println!("{}", remote_control);
+    outputln!("{}", remote_control);
     remote_control.undo_button_was_pushed();
     remote_control.off_button_was_pushed(0);
     remote_control.on_button_was_pushed(0);
-    println!("{}", remote_control);
+    outputln!("{}", remote_control);
     remote_control.undo_button_was_pushed();
 }
 
@@ -367,9 +367,9 @@ fn test_macro_command() {
     let mut remote_control = RemoteControl::new();
     remote_control.set_command(0, Box::new(party_on_command), Box::new(party_off_command));
 
-    println!("{}", remote_control);
-    println!("--- Pushing Macro On ---");
+    outputln!("{}", remote_control);
+    outputln!("--- Pushing Macro On ---");
     remote_control.on_button_was_pushed(0);
-    println!("--- Pushing Macro Off ---");
+    outputln!("--- Pushing Macro Off ---");
     remote_control.off_button_was_pushed(0);
 }
@@ -89,11 +89,14 @@ impl MenuComponent for MenuItem {
         Ok(self.vegetarian)
     }
     fn print(&self) -> Result<(), MenuComponentErr> {
-        print!("{}", self.name);
-        if self.vegetarian {
-            print!("(v)");
-        }
-        println!(", {}    -- {}", self.price, self.description);
+        let vegetarian = if self.vegetarian { "(v)" } else { "" };
+        outputln!(
+            "{}{}, {}    -- {}",
+            self.name,
+            vegetarian,
+            self.price,
+            self.description
+        );
         Ok(())
     }
 }
@@ -145,7 +148,7 @@ impl MenuComponent for Menu {
         Ok(self.description.clone())
     }
     fn print(&self) -> Result<(), MenuComponentErr> {
-        println!("\n{}, {}\n-----------------", self.name, self.description);
+        outputln!("\n{}, {}\n-----------------", self.name, self.description);
         if let Some(ref v) = self.menu_components {
             for i in v {
                 i.print()?
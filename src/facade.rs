@@ -10,19 +10,19 @@
 struct Amplifier;
 impl Amplifier {
     fn on(&self) {
-        println!("Top-O-Line Amplifier on");
+        outputln!("Top-O-Line Amplifier on");
     }
     fn set_dvd(&self, dvd: &DvdPlayer) {
-        println!("Top-O-Line Amplifier setting DVD player to Top-O-Line DVD Player");
+        outputln!("Top-O-Line Amplifier setting DVD player to Top-O-Line DVD Player");
     }
     fn set_surround_sound(&self) {
-        println!("Top-O-Line Amplifier surround sound on");
+        outputln!("Top-O-Line Amplifier surround sound on");
     }
     fn set_volume(&self, volume: usize) {
-        println!("Top-O-Line Amplifier setting volume to {}", volume);
+        outputln!("Top-O-Line Amplifier setting volume to {}", volume);
     }
     fn off(&self) {
-        println!("Top-O-Line Amplifier off");
+        outputln!("Top-O-Line Amplifier off");
     }
 }
 struct Tuner;
@@ -31,63 +31,63 @@ struct DvdPlayer {
 }
 impl DvdPlayer {
     fn on(&self) {
-        println!("Top-O-Line DVD on");
+        outputln!("Top-O-Line DVD on");
     }
     fn stop(&self) {
-        println!("Top-O-Line DVD stopped \"{}\"", self.movie.clone().unwrap());
+        outputln!("Top-O-Line DVD stopped \"{}\"", self.movie.clone().unwrap());
     }
     fn play(&mut self, movie: &str) {
         self.movie = Some(movie.to_string());
-        println!("Top-O-Line DVD play \"{}\"", movie);
+        outputln!("Top-O-Line DVD play \"{}\"", movie);
     }
     fn off(&self) {
-        println!("Top-O-Line DVD off");
+        outputln!("Top-O-Line DVD off");
     }
     fn eject(&self) {
-        println!("Top-O-Line DVD eject");
+        outputln!("Top-O-Line DVD eject");
     }
 }
 struct CdPlayer;
 struct Projector;
 impl Projector {
     fn on(&self) {
-        println!("Top-O-Line Project on");
+        outputln!("Top-O-Line Project on");
     }
     fn wide_screen_mode(&self) {
-        println!("Top-O-Line Project is widescreen mode");
+        outputln!("Top-O-Line Project is widescreen mode");
     }
     fn off(&self) {
-        println!("Top-O-Line Project off");
+        outputln!("Top-O-Line Project off");
     }
 }
 struct TheaterLights;
 impl TheaterLights {
     fn dim(&self, num: usize) {
-        println!("Theater Ceiling Lights dimming to {}%", num);
+        outputln!("Theater Ceiling Lights dimming to {}%", num);
     }
     fn on(&self) {
-        println!("Theater Ceiling Lights on");
+        outputln!("Theater Ceiling Lights on");
     }
 }
 struct Screen;
 impl Screen {
     fn down(&self) {
-        println!("Theater Screen going down");
+        outputln!("Theater Screen going down");
     }
     fn up(&self) {
-        println!("Theater Screen going up");
+        outputln!("Theater Screen going up");
     }
 }
 struct PopcornPopper;
 impl PopcornPopper {
     fn on(&self) {
-        println!("Popcorn Popper on");
+        outputln!("Popcorn Popper on");
     }
     fn off(&self) {
-        println!("Popcorn Popper off");
+        outputln!("Popcorn Popper off");
     }
     fn pop(&self) {
-        println!("Popcorn Popper popping popcorn!");
+        outputln!("Popcorn Popper popping popcorn!");
     }
 }
 
@@ -125,7 +125,7 @@ impl HomeTheaterFacade {
     }
 
     fn watch_movie(&mut self, movie: &str) {
-        println!("Get ready to watch a movie...");
+        outputln!("Get ready to watch a movie...");
         self.popper.on();
         self.popper.pop();
         self.lights.dim(10);
@@ -140,7 +140,7 @@ impl HomeTheaterFacade {
         self.dvd.play(movie);
     }
     fn end_movie(&self) {
-        println!("Shutting movie theater down...");
+        outputln!("Shutting movie theater down...");
         self.popper.off();
         self.lights.on();
         self.screen.up();
@@ -113,13 +113,13 @@ impl PizzaIngredientFactory for ChicagoPizzaIngredientFactory {
 pub trait Pizza {
     fn prepare(&mut self);
     fn bake(&self) {
-        println!("Bake for 25 minutes at 350");
+        outputln!("Bake for 25 minutes at 350");
     }
     fn cut(&self) {
-        println!("Cutting the pizza into diagonal slices");
+        outputln!("Cutting the pizza into diagonal slices");
     }
     fn box_(&self) {
-        println!("Place pizza in official PizzaStore box");
+        outputln!("Place pizza in official PizzaStore box");
     }
     fn name(&self) -> &str;
     fn set_name(&mut self, name: &str);
@@ -158,7 +158,7 @@ impl<F: PizzaIngredientFactory> Pizza for CheesePizza<F> {
         self.name = name.to_string();
     }
     fn prepare(&mut self) {
-        println!("peparing {}", self.name());
+        outputln!("peparing {}", self.name());
         self.dough = Some(self.ingredient_factory.create_dough());
         self.sauce = Some(self.ingredient_factory.create_sauce());
         self.cheese = Some(self.ingredient_factory.create_cheese());
@@ -186,16 +186,16 @@ impl<F: PizzaIngredientFactory> Pizza for CheesePizza<F> {
 //         return &self.name;
 //     }
 //     fn prepare(&self) {
-//         println!("Preparing {}", self.name);
-//         println!("Tossing dough...");
-//         println!("Adding sauch...");
-//         println!("Adding toppings: ");
+//         outputln!("Preparing {}", self.name);
+//         outputln!("Tossing dough...");
+//         outputln!("Adding sauch...");
+//         outputln!("Adding toppings: ");
 //         for i in &self.toppings {
-//             println!("    {}", i);
+//             outputln!("    {}", i);
 //         }
 //     }
 //     fn cut(&self) {
-//         println!("Cutting the pizza into square slices");
+//         outputln!("Cutting the pizza into square slices");
 //     }
 // }
 
@@ -10,13 +10,13 @@
 pub trait Pizza {
     fn prepare(&self);
     fn bake(&self) {
-        println!("Bake for 25 minutes at 350");
+        outputln!("Bake for 25 minutes at 350");
     }
     fn cut(&self) {
-        println!("Cutting the pizza into diagonal slices");
+        outputln!("Cutting the pizza into diagonal slices");
     }
     fn box_(&self) {
-        println!("Place pizza in official PizzaStore box");
+        outputln!("Place pizza in official PizzaStore box");
     }
     fn name(&self) -> &str;
 }
@@ -45,12 +45,12 @@ impl Pizza for NYStyleCheesePizza {
     // rust 的 trait 和 struct 是完全去耦合的，所以 trait 的方法实现无法得知 struct 里面的 field
     // 无法实现这个方法的代码复用
     fn prepare(&self) {
-        println!("Preparing {}", self.name);
-        println!("Tossing dough...");
-        println!("Adding sauch...");
-        println!("Adding toppings: ");
+        outputln!("Preparing {}", self.name);
+        outputln!("Tossing dough...");
+        outputln!("Adding sauch...");
+        outputln!("Adding toppings: ");
         for i in &self.toppings {
-            println!("    {}", i);
+            outputln!("    {}", i);
         }
     }
 }
@@ -76,16 +76,16 @@ impl Pizza for ChicagoStyleCheesePizza {
         return &self.name;
     }
     fn prepare(&self) {
-        println!("Preparing {}", self.name);
-        println!("Tossing dough...");
-        println!("Adding sauch...");
-        println!("Adding toppings: ");
+        outputln!("Preparing {}", self.name);
+        outputln!("Tossing dough...");
+        outputln!("Adding sauch...");
+        outputln!("Adding toppings: ");
         for i in &self.toppings {
-            println!("    {}", i);
+            outputln!("    {}", i);
         }
     }
     fn cut(&self) {
-        println!("Cutting the pizza into square slices");
+        outputln!("Cutting the pizza into square slices");
     }
 }
 
@@ -118,7 +118,7 @@ impl DinerMenu {
     fn add_item(&mut self, name: &str, description: &str, vegetarian: bool, price: f64) {
         let menu_item = MenuItem::new(name, description, vegetarian, price);
         if self.number_of_items >= MAX_ITEMS {
-            eprintln!("Sorry, menu is full! Can't add item to menu");
+            eoutputln!("Sorry, menu is full! Can't add item to menu");
         } else {
             self.menu_items[self.number_of_items] = menu_item;
             self.number_of_items += 1;
@@ -238,16 +238,16 @@ impl Waitress {
         }
     }
     fn print_menu(&self) {
-        println!("MENU\n----BREAKFAST");
+        outputln!("MENU\n----BREAKFAST");
         print(&self.pancake_house_menu);
-        println!("MENU\n----LUNCH");
+        outputln!("MENU\n----LUNCH");
         print(&self.diner_menu);
     }
 }
 
 fn print<'a>(ite: impl IntoIterator<Item = &'a MenuItem, IntoIter = MenuIterator<'a>>) {
     for i in ite {
-        println!("{}, {} -- {}", i.name, i.price, i.description);
+        outputln!("{}, {} -- {}", i.name, i.price, i.description);
     }
 }
 
@@ -1,8 +1,12 @@
-#![feature(vec_remove_item)]
 use lazy_static;
 
+#[macro_use]
+pub mod output;
+
 pub mod observer;
-// pub mod observer_pull;
+pub mod observer_channel;
+pub mod observer_replay;
+pub mod observer_pull;
 pub mod decorator;
 //pub mod factory_abstracted;
 pub mod adapter;
@@ -11,6 +15,7 @@ pub mod facade;
 pub mod factory_method;
 pub mod singleton;
 pub mod strategy;
+pub mod pond;
 pub mod template_method;
 pub mod iterator;
 pub mod composite;
@@ -18,7 +23,9 @@ pub mod state;
 
 use crate::decorator::{Beverage, DarkRost, Espresso, HouseBlend, Mocha, Soy, Whip};
 use crate::factory_method::{ChicagoPizzaStore, NYPizzaStore, Pizza, PizzaStore};
-use crate::observer::{CurrentConditionsDisplay, Subject, WeatherDate};
+use crate::observer::{
+    CurrentConditionsDisplay, Humidity, Pressure, Subject, Temperature, WeatherDate,
+};
 use crate::singleton::{ChocolateBoiler, C};
 use crate::strategy::{Duck, FlyBehavior, FlyRocketPowered, MallardDuck, ModelDuck};
 
@@ -27,16 +34,29 @@ mod tests {
     use super::*;
     #[test]
     fn test_mallard_duck() {
-        let mallard = MallardDuck::new();
-        mallard.perform_quack();
-        mallard.perform_fly();
-        println!("\n");
+        let lines = output::capture(|| {
+            let mallard = MallardDuck::new();
+            mallard.perform_quack();
+            mallard.perform_fly();
+            outputln!("\n");
 
-        let model = ModelDuck::new();
-        model.perform_fly();
-        let r = Box::new(FlyRocketPowered);
-        model.set_fly_behavior(r);
-        model.perform_fly();
+            let model = ModelDuck::new();
+            model.perform_fly();
+            let r = Box::new(FlyRocketPowered);
+            model.set_fly_behavior(r);
+            model.perform_fly();
+        });
+        assert_eq!(
+            lines,
+            vec![
+                "Quack",
+                "I'm flying",
+                "",
+                "",
+                "I can't fly",
+                "I'm flying with a rocket!"
+            ]
+        );
     }
 
     #[test]
@@ -44,30 +64,61 @@ mod tests {
         let weather_data = std::rc::Rc::new(std::cell::RefCell::new(WeatherDate::new()));
         let _ = CurrentConditionsDisplay::new(weather_data.clone());
 
-        weather_data.borrow_mut().set_musurements(80.0, 65.0, 30.4);
-        weather_data.borrow_mut().notify_observers();
-        weather_data.borrow_mut().set_musurements(82.0, 70.0, 29.2);
-        weather_data.borrow_mut().notify_observers();
-        weather_data.borrow_mut().set_musurements(78.0, 90.0, 29.2);
-        weather_data.borrow_mut().notify_observers();
+        // set_musurements 会自动通知观察者
+        let lines = output::capture(|| {
+            assert!(weather_data
+                .borrow_mut()
+                .set_musurements(
+                    Temperature::fahrenheit(80.0),
+                    Humidity::percent(65.0),
+                    Pressure::inhg(30.4),
+                )
+                .is_ok());
+            assert!(weather_data
+                .borrow_mut()
+                .set_musurements(
+                    Temperature::fahrenheit(82.0),
+                    Humidity::percent(70.0),
+                    Pressure::inhg(29.2),
+                )
+                .is_ok());
+            assert!(weather_data
+                .borrow_mut()
+                .set_musurements(
+                    Temperature::fahrenheit(78.0),
+                    Humidity::percent(90.0),
+                    Pressure::inhg(29.2),
+                )
+                .is_ok());
+            // 没有新数据，不会重复通知
+            assert!(weather_data.borrow_mut().notify_observers().is_ok());
+        });
+        assert_eq!(
+            lines,
+            vec![
+                "Current conditions: 80 F degrees and 65 % humidity",
+                "Current conditions: 82 F degrees and 70 % humidity",
+                "Current conditions: 78 F degrees and 90 % humidity"
+            ]
+        );
     }
 
     #[test]
     fn test_decorator() {
         let beravage = Espresso::new();
-        println!("{} ${}", beravage.description(), beravage.cost());
+        outputln!("{} ${}", beravage.description(), beravage.cost());
 
         let beravage2 = DarkRost::new();
         let beravage2 = Mocha::new(Box::new(beravage2));
         let beravage2 = Mocha::new(Box::new(beravage2));
         let beravage2 = Whip::new(Box::new(beravage2));
-        println!("{} ${}", beravage2.description(), beravage2.cost());
+        outputln!("{} ${}", beravage2.description(), beravage2.cost());
 
         let beravage3 = HouseBlend::new();
         let beravage3 = Soy::new(Box::new(beravage3));
         let beravage3 = Mocha::new(Box::new(beravage3));
         let beravage3 = Whip::new(Box::new(beravage3));
-        println!("{} ${}", beravage3.description(), beravage3.cost());
+        outputln!("{} ${}", beravage3.description(), beravage3.cost());
     }
 
     #[test]
@@ -76,21 +127,21 @@ mod tests {
         let chicago_store = ChicagoPizzaStore::new();
 
         let pizza = ny_store.order_pizza("cheese");
-        println!("Ethan ordered a {}\n", pizza.name());
+        outputln!("Ethan ordered a {}\n", pizza.name());
 
         let pizza = chicago_store.order_pizza("cheese");
-        println!("Joel ordered a {}", pizza.name())
+        outputln!("Joel ordered a {}", pizza.name())
     }
 
     #[test]
     fn test_singleton() {
-        println!(
+        outputln!(
             "{} {}",
             C.read().unwrap().is_empty(),
             C.read().unwrap().is_boiled()
         );
         C.write().unwrap().fill();
-        println!(
+        outputln!(
             "{} {}",
             C.read().unwrap().is_empty(),
             C.read().unwrap().is_boiled()
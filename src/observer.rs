@@ -1,114 +1,1627 @@
 //! 观察者订阅某个主题，主题对象管理某些数据，一旦主题内的数据改变，就会通知观察者或者把行数据送到观察者手上
 
-pub trait Subject<T>
-where
-    T: Observer,
-{
-    fn register_observer(&mut self, o: std::rc::Rc<std::cell::RefCell<T>>);
-    fn remove_observer(
-        &mut self,
-        o: std::rc::Rc<std::cell::RefCell<T>>,
-    ) -> Option<std::rc::Rc<std::cell::RefCell<T>>>;
-    fn notify_observers(&mut self);
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
+
+/// 默认保留的历史读数条数
+pub const HISTORY_LIMIT: usize = 64;
+
+/// 1 英寸汞柱等于多少百帕
+const HPA_PER_INHG: f64 = 33.863_886_666_7;
+
+/// 温度，内部统一按华氏度存
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Temperature(f64);
+impl Temperature {
+    pub fn fahrenheit(degrees: f64) -> Self {
+        Temperature(degrees)
+    }
+    pub fn celsius(degrees: f64) -> Self {
+        Temperature(degrees * 9.0 / 5.0 + 32.0)
+    }
+    pub fn as_fahrenheit(self) -> f64 {
+        self.0
+    }
+    pub fn as_celsius(self) -> f64 {
+        (self.0 - 32.0) * 5.0 / 9.0
+    }
+}
+
+/// 相对湿度，百分比
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Humidity(f64);
+impl Humidity {
+    pub fn percent(percent: f64) -> Self {
+        Humidity(percent)
+    }
+    pub fn as_percent(self) -> f64 {
+        self.0
+    }
 }
 
-pub struct WeatherDate<T: Observer> {
-    // 不能用 HashSet，因为它不能 for i in &mut hashset
-    // 存的也是RefCell<T>不能是T
-    observers: Vec<std::rc::Rc<std::cell::RefCell<T>>>,
-    temperature: f64,
-    humidity: f64,
-    pressure: f64,
+/// 气压，内部统一按英寸汞柱存
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Pressure(f64);
+impl Pressure {
+    pub fn inhg(inches: f64) -> Self {
+        Pressure(inches)
+    }
+    pub fn hpa(hectopascals: f64) -> Self {
+        Pressure(hectopascals / HPA_PER_INHG)
+    }
+    pub fn as_inhg(self) -> f64 {
+        self.0
+    }
+    pub fn as_hpa(self) -> f64 {
+        self.0 * HPA_PER_INHG
+    }
 }
 
-impl<O> WeatherDate<O>
-where
-    O: Observer,
-{
+/// display 显示读数时用的单位制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitSystem {
+    /// 华氏度和英寸汞柱
+    #[default]
+    Imperial,
+    /// 摄氏度和百帕
+    Metric,
+}
+impl UnitSystem {
+    /// 换算成这个单位制下的数值，保留一位小数
+    pub fn temperature(self, t: Temperature) -> f64 {
+        match self {
+            UnitSystem::Imperial => round(t.as_fahrenheit()),
+            UnitSystem::Metric => round(t.as_celsius()),
+        }
+    }
+    pub fn temperature_unit(self) -> &'static str {
+        match self {
+            UnitSystem::Imperial => "F",
+            UnitSystem::Metric => "C",
+        }
+    }
+    pub fn pressure(self, p: Pressure) -> f64 {
+        match self {
+            UnitSystem::Imperial => round(p.as_inhg()),
+            UnitSystem::Metric => round(p.as_hpa()),
+        }
+    }
+    pub fn pressure_unit(self) -> &'static str {
+        match self {
+            UnitSystem::Imperial => "inHg",
+            UnitSystem::Metric => "hPa",
+        }
+    }
+}
+
+fn round(v: f64) -> f64 {
+    (v * 10.0).round() / 10.0
+}
+
+/// 注册观察者时得到的订阅 id，在同一个主题内唯一，取消订阅靠它而不是比较观察者的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SubscriptionId(pub(crate) u64);
+
+pub trait Subject {
+    fn register_observer(&mut self, o: Rc<RefCell<dyn Observer>>) -> SubscriptionId;
+    fn remove_observer(&mut self, id: SubscriptionId) -> Option<Rc<RefCell<dyn Observer>>>;
+    /// 只有数据标记为改变过才会通知，通知之后清除标记
+    /// 某个观察者出错或者 panic 不影响其它观察者，失败的都记在返回的报告里
+    fn notify_observers(&mut self) -> NotificationReport;
+}
+
+pub struct WeatherDate {
+    observers: ObserverRegistry,
+    temperature: Temperature,
+    humidity: Humidity,
+    pressure: Pressure,
+    // 和 java.util.Observable 一样，数据改变过才会通知
+    changed: bool,
+    // 大于 0 表示正在批量更新，先不通知
+    batch_depth: usize,
+    // 环形缓冲，满了就丢掉最旧的
+    history: VecDeque<Reading>,
+    history_limit: usize,
+}
+
+impl WeatherDate {
     pub fn new() -> Self {
+        WeatherDate::with_history(HISTORY_LIMIT)
+    }
+    /// 最多保留 capacity 条历史读数，0 表示不记录
+    pub fn with_history(capacity: usize) -> Self {
         WeatherDate {
-            observers: Vec::new(),
-            temperature: 0.0,
-            humidity: 0.0,
-            pressure: 0.0,
+            observers: ObserverRegistry::new(),
+            temperature: Temperature::fahrenheit(0.0),
+            humidity: Humidity::percent(0.0),
+            pressure: Pressure::inhg(0.0),
+            changed: false,
+            batch_depth: 0,
+            history: VecDeque::with_capacity(capacity),
+            history_limit: capacity,
         }
     }
 
-    pub fn set_musurements(&mut self, temprature: f64, humidity: f64, pressure: f64) {
+    pub fn set_musurements(
+        &mut self,
+        temprature: Temperature,
+        humidity: Humidity,
+        pressure: Pressure,
+    ) -> NotificationReport {
+        self.set_musurements_at(temprature, humidity, pressure, SystemTime::now())
+    }
+
+    /// 和 set_musurements 一样，只是历史里记录的是给定的测量时间
+    pub fn set_musurements_at(
+        &mut self,
+        temprature: Temperature,
+        humidity: Humidity,
+        pressure: Pressure,
+        at: SystemTime,
+    ) -> NotificationReport {
         self.temperature = temprature;
         self.humidity = humidity;
         self.pressure = pressure;
-        // todo 不能调用 trait 里面的方法
-        // self.notify_observers();
+        if self.history_limit > 0 {
+            if self.history.len() == self.history_limit {
+                self.history.pop_front();
+            }
+            self.history.push_back(Reading {
+                measurements: self.measurements(),
+                at,
+            });
+        }
+        self.measurements_changed()
     }
-}
 
-impl<T> Subject<T> for WeatherDate<T>
-where
-    T: Observer + std::cmp::PartialEq,
-{
-    fn register_observer(&mut self, o: std::rc::Rc<std::cell::RefCell<T>>) {
-        self.observers.push(o);
+    /// 标记数据已改变，不在批量更新中就立即通知观察者，否则返回空的报告
+    pub fn measurements_changed(&mut self) -> NotificationReport {
+        self.set_changed();
+        if self.batch_depth == 0 {
+            self.notify_observers()
+        } else {
+            NotificationReport::default()
+        }
     }
-    fn remove_observer(
+
+    /// 在 f 里面的多次更新只会在最后通知一次，可以嵌套
+    /// f 里面 panic 了也会退出批量状态，之后的更新照常通知
+    pub fn batch<F: FnOnce(&mut Self)>(&mut self, f: F) -> NotificationReport {
+        self.batch_depth += 1;
+        let guard = BatchGuard(self);
+        f(guard.0);
+        drop(guard);
+        if self.batch_depth == 0 {
+            self.notify_observers()
+        } else {
+            NotificationReport::default()
+        }
+    }
+
+    pub fn set_changed(&mut self) {
+        self.changed = true;
+    }
+    pub fn clear_changed(&mut self) {
+        self.changed = false;
+    }
+    pub fn has_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// 注册观察者，返回的 Subscription 被 drop 时自动取消订阅
+    /// 通知的时候主题已经被借走了，观察者在 update 里要订阅的话用 registry() 拿到的句柄
+    pub fn subscribe(&self, o: Rc<RefCell<dyn Observer>>) -> Subscription {
+        self.observers.subscribe(o)
+    }
+
+    /// 观察者列表的句柄，交给观察者之后它可以在 update 里注册、取消注册或者订阅观察者
+    /// 在 update 里只能用这个句柄，不能再去借主题
+    pub fn registry(&self) -> ObserverRegistry {
+        self.observers.clone()
+    }
+
+    pub fn subscription_of<O: Observer + ?Sized>(
+        &self,
+        o: &Rc<RefCell<O>>,
+    ) -> Option<SubscriptionId> {
+        self.observers.subscription_of(o)
+    }
+    pub fn register_weak_observer<O: Observer + 'static>(
         &mut self,
-        o: std::rc::Rc<std::cell::RefCell<T>>,
-    ) -> Option<std::rc::Rc<std::cell::RefCell<T>>> {
-        self.observers.remove_item(&o)
+        o: &Rc<RefCell<O>>,
+    ) -> SubscriptionId {
+        self.observers.register_weak_observer(o)
+    }
+    pub fn observer_count(&self) -> usize {
+        self.observers.observer_count()
+    }
+    pub fn filter_observer<F: NotificationFilter + 'static>(
+        &mut self,
+        id: SubscriptionId,
+        filter: F,
+    ) -> bool {
+        self.observers.filter_observer(id, filter)
+    }
+    pub fn set_priority(&mut self, id: SubscriptionId, priority: i32) -> bool {
+        self.observers.set_priority(id, priority)
+    }
+
+    pub fn measurements(&self) -> Measurements {
+        Measurements {
+            temperature: self.temperature,
+            humidity: self.humidity,
+            pressure: self.pressure,
+        }
+    }
+
+    /// 历史读数，从旧到新
+    pub fn history(&self) -> Vec<Reading> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// 最近的 n 条读数，从旧到新
+    pub fn last_readings(&self, n: usize) -> Vec<Reading> {
+        let skip = self.history.len().saturating_sub(n);
+        self.history.iter().skip(skip).cloned().collect()
+    }
+
+    /// 测量时间在 [from, to) 之内的读数
+    pub fn readings_between(&self, from: SystemTime, to: SystemTime) -> Vec<Reading> {
+        self.history
+            .iter()
+            .filter(|r| r.at >= from && r.at < to)
+            .cloned()
+            .collect()
+    }
+
+    /// 把最近的 n 条读数按顺序补发给一个观察者，中途加入的 display 马上就有数据可画
+    /// 补发也经过它的过滤条件，每一条读数的结果都记在报告里
+    pub fn replay(&mut self, id: SubscriptionId, n: usize) -> NotificationReport {
+        let mut report = NotificationReport::default();
+        let readings = self.last_readings(n);
+        let d = match self.observers.list.borrow().delivery(id) {
+            Some(d) => d,
+            None => return report,
+        };
+        for r in &readings {
+            if d.accept(&r.measurements) {
+                report.record(id, deliver(&d.observer, &r.measurements));
+            }
+        }
+        report
+    }
+}
+
+enum ObserverRef {
+    Strong(Rc<RefCell<dyn Observer>>),
+    Weak(Weak<RefCell<dyn Observer>>),
+}
+
+impl ObserverRef {
+    fn upgrade(&self) -> Option<Rc<RefCell<dyn Observer>>> {
+        match self {
+            ObserverRef::Strong(o) => Some(o.clone()),
+            ObserverRef::Weak(o) => o.upgrade(),
+        }
+    }
+    fn is_alive(&self) -> bool {
+        match self {
+            ObserverRef::Strong(_) => true,
+            ObserverRef::Weak(o) => o.strong_count() > 0,
+        }
+    }
+}
+
+type SharedFilter = Rc<RefCell<Box<dyn NotificationFilter>>>;
+
+/// drop 的时候退出一层批量更新
+struct BatchGuard<'a>(&'a mut WeatherDate);
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.batch_depth -= 1;
+    }
+}
+
+struct ObserverEntry {
+    id: SubscriptionId,
+    observer: ObserverRef,
+    filter: Option<SharedFilter>,
+    priority: i32,
+}
+
+/// 通知开始时给每个观察者取的快照，投递的时候不再借用观察者列表
+struct Delivery {
+    id: SubscriptionId,
+    observer: Rc<RefCell<dyn Observer>>,
+    filter: Option<SharedFilter>,
+}
+
+impl Delivery {
+    fn accept(&self, m: &Measurements) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|f| f.borrow_mut().accept(m))
+    }
+}
+
+// 不能用 HashSet，因为它不能 for i in &mut hashset
+// 存的是 trait object，这样不同类型的观察者可以注册到同一个主题上
+// 按优先级从高到低排，同一优先级按注册的先后
+#[derive(Default)]
+struct ObserverList {
+    entries: Vec<ObserverEntry>,
+    next_id: u64,
+}
+
+impl ObserverList {
+    fn push(&mut self, observer: ObserverRef) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(ObserverEntry {
+            id,
+            observer,
+            filter: None,
+            priority: 0,
+        });
+        self.sort();
+        id
     }
-    fn notify_observers(&mut self) {
-        for i in &self.observers {
-            i.borrow_mut()
-                .update(self.temperature, self.humidity, self.pressure);
+
+    fn sort(&mut self) {
+        // id 是递增的，所以同一优先级内就是注册顺序
+        self.entries.sort_by_key(|e| (Reverse(e.priority), e.id));
+    }
+
+    fn entry_mut(&mut self, id: SubscriptionId) -> Option<&mut ObserverEntry> {
+        self.entries.iter_mut().find(|e| e.id == id)
+    }
+
+    fn contains(&self, id: SubscriptionId) -> bool {
+        self.entries.iter().any(|e| e.id == id)
+    }
+
+    fn delivery(&self, id: SubscriptionId) -> Option<Delivery> {
+        let e = self.entries.iter().find(|e| e.id == id)?;
+        Some(Delivery {
+            id,
+            observer: e.observer.upgrade()?,
+            filter: e.filter.clone(),
+        })
+    }
+
+    /// 顺便清理掉已经被 drop 的弱引用观察者
+    fn snapshot(&mut self) -> Vec<Delivery> {
+        self.entries.retain(|e| e.observer.is_alive());
+        let ids: Vec<SubscriptionId> = self.entries.iter().map(|e| e.id).collect();
+        ids.into_iter().filter_map(|id| self.delivery(id)).collect()
+    }
+}
+
+/// 观察者列表的句柄，clone 出来的共享同一份列表
+/// 通知过程中可以放心地注册和取消注册：取消立即生效，这一轮还没轮到的就不会再收到
+/// 新注册的从下一轮通知开始收到
+#[derive(Clone, Default)]
+pub struct ObserverRegistry {
+    list: Rc<RefCell<ObserverList>>,
+}
+
+impl ObserverRegistry {
+    pub fn new() -> Self {
+        ObserverRegistry::default()
+    }
+
+    pub fn register_observer(&self, o: Rc<RefCell<dyn Observer>>) -> SubscriptionId {
+        self.list.borrow_mut().push(ObserverRef::Strong(o))
+    }
+
+    /// 只存观察者的 Weak，主题不会让它一直活着，应用把它 drop 掉之后下次通知时就被清理掉
+    pub fn register_weak_observer<O: Observer + 'static>(
+        &self,
+        o: &Rc<RefCell<O>>,
+    ) -> SubscriptionId {
+        // clone 出来的 Rc 和 o 指向同一个观察者，它的 Weak 跟着 o 的生命周期走
+        let o: Rc<RefCell<dyn Observer>> = o.clone();
+        self.list
+            .borrow_mut()
+            .push(ObserverRef::Weak(Rc::downgrade(&o)))
+    }
+
+    /// 弱引用的观察者已经被 drop 掉时也会移除，只是返回 None
+    pub fn remove_observer(&self, id: SubscriptionId) -> Option<Rc<RefCell<dyn Observer>>> {
+        let mut list = self.list.borrow_mut();
+        let i = list.entries.iter().position(|e| e.id == id)?;
+        list.entries.remove(i).observer.upgrade()
+    }
+
+    /// 注册观察者，返回的 Subscription 被 drop 时自动取消订阅
+    pub fn subscribe(&self, o: Rc<RefCell<dyn Observer>>) -> Subscription {
+        Subscription {
+            id: self.register_observer(o),
+            list: Rc::downgrade(&self.list),
+        }
+    }
+
+    /// 优先级高的先通知，默认是 0，可以是负数，id 不存在返回 false
+    pub fn set_priority(&self, id: SubscriptionId, priority: i32) -> bool {
+        let mut list = self.list.borrow_mut();
+        match list.entry_mut(id) {
+            Some(e) => e.priority = priority,
+            None => return false,
+        }
+        list.sort();
+        true
+    }
+
+    /// 给已经注册的观察者设置过滤条件，替换之前的那个，id 不存在返回 false
+    pub fn filter_observer<F: NotificationFilter + 'static>(
+        &self,
+        id: SubscriptionId,
+        filter: F,
+    ) -> bool {
+        match self.list.borrow_mut().entry_mut(id) {
+            Some(e) => {
+                e.filter = Some(Rc::new(RefCell::new(Box::new(filter))));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 按 Rc 的指向查找观察者的订阅 id，用于找回在构造函数里注册的那些 display
+    pub fn subscription_of<O: Observer + ?Sized>(
+        &self,
+        o: &Rc<RefCell<O>>,
+    ) -> Option<SubscriptionId> {
+        // 只比较数据地址，具体类型和 dyn Observer 的 Rc 都能用来查
+        let target = Rc::as_ptr(o) as *const ();
+        self.list
+            .borrow()
+            .entries
+            .iter()
+            .find(|e| {
+                e.observer
+                    .upgrade()
+                    .is_some_and(|x| Rc::as_ptr(&x) as *const () == target)
+            })
+            .map(|e| e.id)
+    }
+
+    /// 还活着的观察者数量，已经被 drop 但还没清理掉的弱引用不算在内
+    pub fn observer_count(&self) -> usize {
+        self.list
+            .borrow()
+            .entries
+            .iter()
+            .filter(|e| e.observer.is_alive())
+            .count()
+    }
+}
+
+/// 一次通知里送出去的三项读数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurements {
+    pub temperature: Temperature,
+    pub humidity: Humidity,
+    pub pressure: Pressure,
+}
+
+/// 历史里的一条读数和它的测量时间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub measurements: Measurements,
+    pub at: SystemTime,
+}
+
+/// 通知之前由主题调用，返回 false 就不调用这个观察者的 update
+pub trait NotificationFilter {
+    fn accept(&mut self, m: &Measurements) -> bool;
+}
+
+impl<F: FnMut(&Measurements) -> bool> NotificationFilter for F {
+    fn accept(&mut self, m: &Measurements) -> bool {
+        self(m)
+    }
+}
+
+/// 死区过滤，和上一次送出去的读数相比，某一项变化超过它的阈值才通知
+/// 阈值是变化量，用 imperial 或 metric 按对应的单位给，是 None 的那一项不关心，第一次总是通知
+pub struct Deadband {
+    // 内部统一按华氏度、湿度百分点和英寸汞柱存
+    temperature: Option<f64>,
+    humidity: Option<f64>,
+    pressure: Option<f64>,
+    last: Option<Measurements>,
+}
+
+impl Deadband {
+    /// 温度按华氏度，湿度按百分点，气压按英寸汞柱
+    pub fn imperial(
+        temperature: Option<f64>,
+        humidity: Option<f64>,
+        pressure: Option<f64>,
+    ) -> Self {
+        Deadband {
+            temperature,
+            humidity,
+            pressure,
+            last: None,
+        }
+    }
+    /// 温度按摄氏度，湿度按百分点，气压按百帕
+    /// 这里是温差，1 摄氏度的变化等于 1.8 华氏度，不加 32
+    pub fn metric(temperature: Option<f64>, humidity: Option<f64>, pressure: Option<f64>) -> Self {
+        Deadband::imperial(
+            temperature.map(|t| t * 9.0 / 5.0),
+            humidity,
+            pressure.map(|p| p / HPA_PER_INHG),
+        )
+    }
+}
+
+impl NotificationFilter for Deadband {
+    fn accept(&mut self, m: &Measurements) -> bool {
+        let moved =
+            |band: Option<f64>, old: f64, new: f64| band.is_some_and(|b| (new - old).abs() > b);
+        let accept = match self.last {
+            None => true,
+            Some(last) => {
+                moved(
+                    self.temperature,
+                    last.temperature.as_fahrenheit(),
+                    m.temperature.as_fahrenheit(),
+                ) || moved(
+                    self.humidity,
+                    last.humidity.as_percent(),
+                    m.humidity.as_percent(),
+                ) || moved(self.pressure, last.pressure.as_inhg(), m.pressure.as_inhg())
+            }
+        };
+        if accept {
+            self.last = Some(*m);
+        }
+        accept
+    }
+}
+
+/// 订阅守卫，离开作用域时自动取消订阅，主题已经不在了就什么都不做
+/// 持有的是观察者列表而不是主题，所以在通知过程中 drop 也没问题
+pub struct Subscription {
+    id: SubscriptionId,
+    list: Weak<RefCell<ObserverList>>,
+}
+
+impl Subscription {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+    /// 放弃守卫，观察者会一直保持订阅，之后可以用 id 手动取消
+    pub fn detach(mut self) -> SubscriptionId {
+        self.list = Weak::new();
+        self.id
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(list) = self.list.upgrade() {
+            ObserverRegistry { list }.remove_observer(self.id);
+        }
+    }
+}
+
+impl Default for WeatherDate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Subject for WeatherDate {
+    fn register_observer(&mut self, o: Rc<RefCell<dyn Observer>>) -> SubscriptionId {
+        self.observers.register_observer(o)
+    }
+    fn remove_observer(&mut self, id: SubscriptionId) -> Option<Rc<RefCell<dyn Observer>>> {
+        self.observers.remove_observer(id)
+    }
+    fn notify_observers(&mut self) -> NotificationReport {
+        let mut report = NotificationReport::default();
+        if !self.has_changed() {
+            return report;
+        }
+        self.clear_changed();
+        let m = self.measurements();
+        let deliveries = self.observers.list.borrow_mut().snapshot();
+        for d in deliveries {
+            // 这一轮里被前面的观察者取消掉的不再通知
+            if !self.observers.list.borrow().contains(d.id) {
+                continue;
+            }
+            if d.accept(&m) {
+                report.record(d.id, deliver(&d.observer, &m));
+            }
+        }
+        report
+    }
+}
+
+/// 调用一个观察者的 update，panic 也拦下来变成失败原因
+fn deliver(o: &Rc<RefCell<dyn Observer>>, m: &Measurements) -> Result<(), ObserverFailure> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        o.borrow_mut().update(m.temperature, m.humidity, m.pressure)
+    }));
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(ObserverFailure::Error(e.to_string())),
+        Err(payload) => {
+            let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "unknown panic".to_string()
+            };
+            Err(ObserverFailure::Panic(message))
+        }
+    }
+}
+
+/// 观察者处理通知失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObserverFailure {
+    /// update 返回了 Err
+    Error(String),
+    /// update 里 panic 了
+    Panic(String),
+}
+impl Display for ObserverFailure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ObserverFailure::Error(e) => write!(f, "observer returned an error: {}", e),
+            ObserverFailure::Panic(e) => write!(f, "observer panicked: {}", e),
+        }
+    }
+}
+impl Error for ObserverFailure {}
+
+/// 一轮通知的结果，delivered 是成功处理的次数，failures 按通知的顺序排列
+#[must_use = "observer failures are only reported here"]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NotificationReport {
+    pub delivered: usize,
+    pub failures: Vec<(SubscriptionId, ObserverFailure)>,
+}
+impl NotificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn record(&mut self, id: SubscriptionId, result: Result<(), ObserverFailure>) {
+        match result {
+            Ok(()) => self.delivered += 1,
+            Err(e) => self.failures.push((id, e)),
         }
     }
 }
 
 pub trait Observer {
-    fn update(&mut self, temprature: f64, humidity: f64, pressure: f64);
+    /// 返回 Err 表示这个观察者没能处理这次通知，不影响其它观察者
+    fn update(
+        &mut self,
+        temprature: Temperature,
+        humidity: Humidity,
+        pressure: Pressure,
+    ) -> Result<(), Box<dyn Error>>;
 }
 
 /// 观察者
 pub struct CurrentConditionsDisplay {
-    temperature: f64,
-    humidity: f64,
-    weather_data: std::rc::Weak<std::cell::RefCell<WeatherDate<Self>>>,
-}
-impl PartialEq for CurrentConditionsDisplay {
-    fn eq(&self, other: &Self) -> bool {
-        self.temperature == other.temperature && self.humidity == other.humidity
-    }
+    temperature: Temperature,
+    humidity: Humidity,
+    units: UnitSystem,
+    weather_data: Weak<RefCell<WeatherDate>>,
 }
 
 impl CurrentConditionsDisplay {
-    pub fn new(
-        weather_data: std::rc::Rc<std::cell::RefCell<WeatherDate<Self>>>,
-    ) -> std::rc::Rc<std::cell::RefCell<Self>> {
+    pub fn new(weather_data: Rc<RefCell<WeatherDate>>) -> Rc<RefCell<Self>> {
+        CurrentConditionsDisplay::with_units(weather_data, UnitSystem::Imperial)
+    }
+    pub fn with_units(
+        weather_data: Rc<RefCell<WeatherDate>>,
+        units: UnitSystem,
+    ) -> Rc<RefCell<Self>> {
         // 这里有循环引用，一对多，一里面存的是多的 Rc，多存的是一的 Weak
-        let s = CurrentConditionsDisplay {
-            temperature: 0.0,
-            humidity: 0.0,
-            weather_data: std::rc::Rc::downgrade(&weather_data),
-        };
-        let p = std::rc::Rc::new(std::cell::RefCell::new(s));
+        let p = CurrentConditionsDisplay::detached(units);
+        p.borrow_mut().weather_data = Rc::downgrade(&weather_data);
         weather_data.borrow_mut().register_observer(p.clone());
         return p;
     }
+    /// 不注册到任何主题，由调用方决定怎么注册（弱引用、订阅守卫等）
+    pub fn detached(units: UnitSystem) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(CurrentConditionsDisplay {
+            temperature: Temperature::fahrenheit(0.0),
+            humidity: Humidity::percent(0.0),
+            units,
+            weather_data: Weak::new(),
+        }))
+    }
 }
 
 impl Observer for CurrentConditionsDisplay {
-    fn update(&mut self, temperature: f64, humidity: f64, _: f64) {
+    fn update(
+        &mut self,
+        temperature: Temperature,
+        humidity: Humidity,
+        _: Pressure,
+    ) -> Result<(), Box<dyn Error>> {
         self.temperature = temperature;
         self.humidity = humidity;
-        println!("{}", self)
+        outputln!("{}", self);
+        Ok(())
     }
 }
 impl std::fmt::Display for CurrentConditionsDisplay {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "Current conditions: {} F degrees and {} % humidity",
-            self.temperature, self.humidity
+            "Current conditions: {} {} degrees and {} % humidity",
+            self.units.temperature(self.temperature),
+            self.units.temperature_unit(),
+            round(self.humidity.as_percent())
         )
     }
 }
+
+/// 温度统计，记录最低、最高和平均温度，内部按华氏度累计
+pub struct StatisticsDisplay {
+    min_temperature: f64,
+    max_temperature: f64,
+    temperature_sum: f64,
+    readings: usize,
+    units: UnitSystem,
+}
+
+impl StatisticsDisplay {
+    pub fn new(weather_data: Rc<RefCell<WeatherDate>>) -> Rc<RefCell<Self>> {
+        StatisticsDisplay::with_units(weather_data, UnitSystem::Imperial)
+    }
+    pub fn with_units(
+        weather_data: Rc<RefCell<WeatherDate>>,
+        units: UnitSystem,
+    ) -> Rc<RefCell<Self>> {
+        let p = StatisticsDisplay::detached(units);
+        weather_data.borrow_mut().register_observer(p.clone());
+        p
+    }
+    /// 不注册到任何主题，由调用方决定怎么注册（弱引用、订阅守卫等）
+    pub fn detached(units: UnitSystem) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(StatisticsDisplay {
+            min_temperature: f64::INFINITY,
+            max_temperature: f64::NEG_INFINITY,
+            temperature_sum: 0.0,
+            readings: 0,
+            units,
+        }))
+    }
+
+    /// 还没有收到数据时返回 None
+    pub fn min_temperature(&self) -> Option<Temperature> {
+        self.stat(self.min_temperature)
+    }
+    pub fn max_temperature(&self) -> Option<Temperature> {
+        self.stat(self.max_temperature)
+    }
+    pub fn average_temperature(&self) -> Option<Temperature> {
+        self.stat(self.temperature_sum / self.readings as f64)
+    }
+    pub fn readings(&self) -> usize {
+        self.readings
+    }
+
+    fn stat(&self, value: f64) -> Option<Temperature> {
+        if self.readings == 0 {
+            None
+        } else {
+            Some(Temperature::fahrenheit(value))
+        }
+    }
+}
+
+impl Observer for StatisticsDisplay {
+    fn update(
+        &mut self,
+        temperature: Temperature,
+        _: Humidity,
+        _: Pressure,
+    ) -> Result<(), Box<dyn Error>> {
+        let temperature = temperature.as_fahrenheit();
+        self.min_temperature = self.min_temperature.min(temperature);
+        self.max_temperature = self.max_temperature.max(temperature);
+        self.temperature_sum += temperature;
+        self.readings += 1;
+        outputln!("{}", self);
+        Ok(())
+    }
+}
+impl std::fmt::Display for StatisticsDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (
+            self.average_temperature(),
+            self.max_temperature(),
+            self.min_temperature(),
+        ) {
+            (Some(avg), Some(max), Some(min)) => write!(
+                f,
+                "Avg/Max/Min temperature = {}/{}/{}",
+                self.units.temperature(avg),
+                self.units.temperature(max),
+                self.units.temperature(min)
+            ),
+            _ => write!(f, "Avg/Max/Min temperature = -/-/-"),
+        }
+    }
+}
+
+/// 根据气压的变化趋势给出的天气预报
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Forecast {
+    Improving,
+    Same,
+    Worse,
+}
+impl std::fmt::Display for Forecast {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Forecast::Improving => write!(f, "Improving weather on the way!"),
+            Forecast::Same => write!(f, "More of the same"),
+            Forecast::Worse => write!(f, "Watch out for cooler, rainy weather"),
+        }
+    }
+}
+
+/// 气压上升天气变好，下降天气变差
+pub struct ForecastDisplay {
+    current_pressure: Pressure,
+    last_pressure: Pressure,
+}
+
+impl ForecastDisplay {
+    pub fn new(weather_data: Rc<RefCell<WeatherDate>>) -> Rc<RefCell<Self>> {
+        let p = ForecastDisplay::detached();
+        weather_data.borrow_mut().register_observer(p.clone());
+        p
+    }
+    /// 不注册到任何主题
+    pub fn detached() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(ForecastDisplay {
+            current_pressure: Pressure::inhg(29.92),
+            last_pressure: Pressure::inhg(29.92),
+        }))
+    }
+
+    pub fn forecast(&self) -> Forecast {
+        if self.current_pressure > self.last_pressure {
+            Forecast::Improving
+        } else if self.current_pressure < self.last_pressure {
+            Forecast::Worse
+        } else {
+            Forecast::Same
+        }
+    }
+}
+
+impl Observer for ForecastDisplay {
+    fn update(
+        &mut self,
+        _: Temperature,
+        _: Humidity,
+        pressure: Pressure,
+    ) -> Result<(), Box<dyn Error>> {
+        self.last_pressure = self.current_pressure;
+        self.current_pressure = pressure;
+        outputln!("{}", self);
+        Ok(())
+    }
+}
+impl std::fmt::Display for ForecastDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Forecast: {}", self.forecast())
+    }
+}
+
+/// 体感温度，由温度和湿度算出来
+pub struct HeatIndexDisplay {
+    heat_index: Temperature,
+    units: UnitSystem,
+}
+
+impl HeatIndexDisplay {
+    pub fn new(weather_data: Rc<RefCell<WeatherDate>>) -> Rc<RefCell<Self>> {
+        HeatIndexDisplay::with_units(weather_data, UnitSystem::Imperial)
+    }
+    pub fn with_units(
+        weather_data: Rc<RefCell<WeatherDate>>,
+        units: UnitSystem,
+    ) -> Rc<RefCell<Self>> {
+        let p = HeatIndexDisplay::detached(units);
+        weather_data.borrow_mut().register_observer(p.clone());
+        p
+    }
+    /// 不注册到任何主题
+    pub fn detached(units: UnitSystem) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(HeatIndexDisplay {
+            heat_index: Temperature::fahrenheit(0.0),
+            units,
+        }))
+    }
+
+    pub fn heat_index(&self) -> Temperature {
+        self.heat_index
+    }
+}
+
+/// 华氏温度和相对湿度的体感温度公式
+pub fn compute_heat_index(t: f64, rh: f64) -> f64 {
+    16.923 + (0.185212 * t) + (5.37941 * rh) - (0.100254 * t * rh)
+        + (0.00941695 * (t * t))
+        + (0.00728898 * (rh * rh))
+        + (0.000345372 * (t * t * rh))
+        - (0.000814971 * (t * rh * rh))
+        + (0.0000102102 * (t * t * rh * rh))
+        - (0.000038646 * (t * t * t))
+        + (0.0000291583 * (rh * rh * rh))
+        + (0.00000142721 * (t * t * t * rh))
+        + (0.000000197483 * (t * rh * rh * rh))
+        - (0.0000000218429 * (t * t * t * rh * rh))
+        + (0.000000000843296 * (t * t * rh * rh * rh))
+        - (0.0000000000481975 * (t * t * t * rh * rh * rh))
+}
+
+impl Observer for HeatIndexDisplay {
+    fn update(
+        &mut self,
+        temperature: Temperature,
+        humidity: Humidity,
+        _: Pressure,
+    ) -> Result<(), Box<dyn Error>> {
+        self.heat_index = Temperature::fahrenheit(compute_heat_index(
+            temperature.as_fahrenheit(),
+            humidity.as_percent(),
+        ));
+        outputln!("{}", self);
+        Ok(())
+    }
+}
+impl std::fmt::Display for HeatIndexDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let heat_index = match self.units {
+            UnitSystem::Imperial => self.heat_index.as_fahrenheit(),
+            UnitSystem::Metric => self.heat_index.as_celsius(),
+        };
+        write!(f, "Heat index is {:.5}", heat_index)
+    }
+}
+
+/// 测试里用的读数，温度按华氏度，湿度按百分比，气压按英寸汞柱
+#[cfg(test)]
+fn set(w: &mut WeatherDate, t: f64, h: f64, p: f64) -> NotificationReport {
+    w.set_musurements(
+        Temperature::fahrenheit(t),
+        Humidity::percent(h),
+        Pressure::inhg(p),
+    )
+}
+
+#[test]
+fn test_heterogeneous_observers() {
+    use crate::output;
+
+    /// 另一种完全不同的观察者，只记下收到的气压
+    struct PressureLog {
+        pressures: Vec<f64>,
+    }
+    impl Observer for PressureLog {
+        fn update(
+            &mut self,
+            _: Temperature,
+            _: Humidity,
+            pressure: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            self.pressures.push(pressure.as_inhg());
+            Ok(())
+        }
+    }
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let current = CurrentConditionsDisplay::new(weather_data.clone());
+    let log = Rc::new(RefCell::new(PressureLog {
+        pressures: Vec::new(),
+    }));
+    weather_data.borrow_mut().register_observer(log.clone());
+
+    let lines =
+        output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(
+        lines,
+        vec!["Current conditions: 80 F degrees and 65 % humidity"]
+    );
+    assert_eq!(log.borrow().pressures, vec![30.4]);
+
+    let id = weather_data.borrow().subscription_of(&current).unwrap();
+    assert!(weather_data.borrow_mut().remove_observer(id).is_some());
+    let lines =
+        output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 82.0, 70.0, 29.2).is_ok()));
+    assert!(lines.is_empty());
+    assert_eq!(log.borrow().pressures, vec![30.4, 29.2]);
+}
+
+#[test]
+fn test_weather_station_displays() {
+    use crate::output;
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let _current = CurrentConditionsDisplay::new(weather_data.clone());
+    let statistics = StatisticsDisplay::new(weather_data.clone());
+    let forecast = ForecastDisplay::new(weather_data.clone());
+    let heat_index = HeatIndexDisplay::new(weather_data.clone());
+    assert_eq!(statistics.borrow().average_temperature(), None);
+
+    let lines =
+        output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(
+        lines,
+        vec![
+            "Current conditions: 80 F degrees and 65 % humidity",
+            "Avg/Max/Min temperature = 80/80/80",
+            "Forecast: Improving weather on the way!",
+            "Heat index is 82.95535",
+        ]
+    );
+
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 82.0, 70.0, 29.2).is_ok()));
+    assert_eq!(forecast.borrow().forecast(), Forecast::Worse);
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 78.0, 90.0, 29.2).is_ok()));
+    assert_eq!(forecast.borrow().forecast(), Forecast::Same);
+
+    let statistics = statistics.borrow();
+    assert_eq!(statistics.readings(), 3);
+    assert_eq!(
+        statistics.min_temperature(),
+        Some(Temperature::fahrenheit(78.0))
+    );
+    assert_eq!(
+        statistics.max_temperature(),
+        Some(Temperature::fahrenheit(82.0))
+    );
+    assert_eq!(
+        statistics.average_temperature(),
+        Some(Temperature::fahrenheit(80.0))
+    );
+    assert_eq!(
+        format!("{:.5}", heat_index.borrow().heat_index().as_fahrenheit()),
+        "83.64967"
+    );
+}
+
+#[test]
+fn test_measurements_changed() {
+    use crate::output;
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let statistics = StatisticsDisplay::new(weather_data.clone());
+
+    // 没有改变过的数据不会通知
+    let lines = output::capture(|| {
+        assert!(weather_data.borrow_mut().notify_observers().is_ok());
+    });
+    assert!(lines.is_empty());
+
+    let lines = output::capture(|| {
+        assert!(weather_data
+            .borrow_mut()
+            .batch(|w| {
+                assert!(set(w, 80.0, 65.0, 30.4).is_ok());
+                assert!(set(w, 82.0, 70.0, 29.2).is_ok());
+                assert!(w.has_changed());
+            })
+            .is_ok());
+    });
+    // 只通知了一次，拿到的是最后一次的数据
+    assert_eq!(lines, vec!["Avg/Max/Min temperature = 82/82/82"]);
+    assert!(!weather_data.borrow().has_changed());
+
+    let lines = output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        assert!(w
+            .batch(|w| {
+                assert!(set(w, 78.0, 90.0, 29.2).is_ok());
+                w.clear_changed();
+            })
+            .is_ok());
+        w.set_changed();
+        assert!(w.notify_observers().is_ok());
+        assert!(w.notify_observers().is_ok());
+    });
+    assert_eq!(lines, vec!["Avg/Max/Min temperature = 80/82/78"]);
+    assert_eq!(statistics.borrow().readings(), 2);
+
+    // batch 里 panic 之后不会一直停在批量状态
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        assert!(weather_data
+            .borrow_mut()
+            .batch(|_| panic!("sensor offline"))
+            .is_ok());
+    }));
+    assert!(result.is_err());
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(statistics.borrow().readings(), 3);
+}
+
+#[test]
+fn test_subscription() {
+    use crate::output;
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    // 两个读数相同的 display，按 id 取消订阅不会删错
+    let first = StatisticsDisplay::detached(UnitSystem::Imperial);
+    let second = StatisticsDisplay::new(weather_data.clone());
+    let first_id = weather_data.borrow_mut().register_observer(first.clone());
+    let second_id = weather_data.borrow().subscription_of(&second).unwrap();
+    assert_ne!(first_id, second_id);
+
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert!(weather_data
+        .borrow_mut()
+        .remove_observer(first_id)
+        .is_some());
+    assert!(weather_data
+        .borrow_mut()
+        .remove_observer(first_id)
+        .is_none());
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 82.0, 70.0, 29.2).is_ok()));
+    assert_eq!(first.borrow().readings(), 1);
+    assert_eq!(second.borrow().readings(), 2);
+
+    {
+        let _guard = weather_data.borrow().subscribe(first.clone());
+        output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 78.0, 90.0, 29.2).is_ok()));
+        assert_eq!(first.borrow().readings(), 2);
+    }
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(first.borrow().readings(), 2);
+
+    let id = weather_data.borrow().subscribe(first.clone()).detach();
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(first.borrow().readings(), 3);
+    assert!(weather_data.borrow_mut().remove_observer(id).is_some());
+
+    // 主题先被 drop 掉，守卫 drop 时什么都不做
+    let guard = weather_data.borrow().subscribe(first);
+    drop(weather_data);
+    drop(guard);
+}
+
+#[test]
+fn test_weak_observer() {
+    use crate::output;
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let strong = StatisticsDisplay::new(weather_data.clone());
+    let weak = StatisticsDisplay::detached(UnitSystem::Imperial);
+    let weak_id = weather_data.borrow_mut().register_weak_observer(&weak);
+    assert_eq!(weather_data.borrow().observer_count(), 2);
+    assert_eq!(weather_data.borrow().subscription_of(&weak), Some(weak_id));
+
+    output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(weak.borrow().readings(), 1);
+
+    // 应用 drop 掉之后不再收到通知，也不再计数
+    drop(weak);
+    assert_eq!(weather_data.borrow().observer_count(), 1);
+    let lines =
+        output::capture(|| assert!(set(&mut weather_data.borrow_mut(), 82.0, 70.0, 29.2).is_ok()));
+    assert_eq!(lines.len(), 1);
+    assert_eq!(strong.borrow().readings(), 2);
+    assert_eq!(
+        weather_data.borrow().observers.list.borrow().entries.len(),
+        1
+    );
+    assert!(weather_data.borrow_mut().remove_observer(weak_id).is_none());
+
+    // 应用关掉的当前状况面板不会再输出
+    let current = CurrentConditionsDisplay::detached(UnitSystem::Metric);
+    let forecast = ForecastDisplay::detached();
+    let heat_index = HeatIndexDisplay::detached(UnitSystem::Imperial);
+    let mut w = weather_data.borrow_mut();
+    w.register_weak_observer(&current);
+    w.register_weak_observer(&forecast);
+    w.register_weak_observer(&heat_index);
+    assert_eq!(w.observer_count(), 4);
+    let lines = output::capture(|| assert!(set(&mut w, 80.0, 65.0, 30.4).is_ok()));
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[1],
+        "Current conditions: 26.7 C degrees and 65 % humidity"
+    );
+    drop(current);
+    let lines = output::capture(|| assert!(set(&mut w, 82.0, 70.0, 29.2).is_ok()));
+    assert_eq!(lines.len(), 3);
+    assert!(!lines.iter().any(|l| l.starts_with("Current conditions")));
+    assert_eq!(w.observer_count(), 3);
+    assert_eq!(forecast.borrow().forecast(), Forecast::Worse);
+}
+
+#[test]
+fn test_filtered_observer() {
+    use crate::output;
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let all = StatisticsDisplay::new(weather_data.clone());
+    let dashboard = StatisticsDisplay::new(weather_data.clone());
+    let humid = StatisticsDisplay::new(weather_data.clone());
+    let find =
+        |o: &Rc<RefCell<StatisticsDisplay>>| weather_data.borrow().subscription_of(o).unwrap();
+    let (dashboard_id, humid_id) = (find(&dashboard), find(&humid));
+    let mut w = weather_data.borrow_mut();
+    assert!(w.filter_observer(dashboard_id, Deadband::imperial(Some(1.0), None, None)));
+    assert!(w.filter_observer(humid_id, |m: &Measurements| m.humidity
+        > Humidity::percent(80.0)));
+    drop(w);
+
+    output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        assert!(set(&mut w, 80.0, 65.0, 30.4).is_ok());
+        // 温度只变了半度，湿度大变也不管
+        assert!(set(&mut w, 80.5, 90.0, 29.2).is_ok());
+        // 和上一次送出去的 80.0 比已经超过一度
+        assert!(set(&mut w, 81.2, 70.0, 29.2).is_ok());
+    });
+    assert_eq!(all.borrow().readings(), 3);
+    assert_eq!(dashboard.borrow().readings(), 2);
+    assert_eq!(
+        dashboard.borrow().max_temperature(),
+        Some(Temperature::fahrenheit(81.2))
+    );
+    assert_eq!(humid.borrow().readings(), 1);
+    assert_eq!(
+        humid.borrow().max_temperature(),
+        Some(Temperature::fahrenheit(80.5))
+    );
+
+    let gone = weather_data.borrow_mut().register_observer(all.clone());
+    weather_data.borrow_mut().remove_observer(gone);
+    assert!(!weather_data
+        .borrow_mut()
+        .filter_observer(gone, |_: &Measurements| true));
+
+    // 公制的阈值是摄氏温差和百帕，1 摄氏度的温差是 1.8 华氏度
+    let reading = |c: f64, hpa: f64| Measurements {
+        temperature: Temperature::celsius(c),
+        humidity: Humidity::percent(65.0),
+        pressure: Pressure::hpa(hpa),
+    };
+    let mut band = Deadband::metric(Some(1.0), None, Some(2.0));
+    assert!(band.accept(&reading(20.0, 1013.0)));
+    assert!(!band.accept(&reading(20.9, 1014.5)));
+    assert!(band.accept(&reading(21.1, 1013.0)));
+    assert!(band.accept(&reading(21.1, 1015.5)));
+    let mut band = Deadband::imperial(Some(1.0), None, None);
+    assert!(band.accept(&reading(20.0, 1013.0)));
+    assert!(band.accept(&reading(20.9, 1013.0)));
+}
+
+#[test]
+fn test_measurement_history() {
+    use crate::output;
+    use std::time::Duration;
+
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let at = |minutes: u64| start + Duration::from_secs(minutes * 60);
+    let weather_data = Rc::new(RefCell::new(WeatherDate::with_history(3)));
+    output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        assert!(w
+            .set_musurements_at(
+                Temperature::fahrenheit(80.0),
+                Humidity::percent(65.0),
+                Pressure::inhg(30.4),
+                at(0),
+            )
+            .is_ok());
+        assert!(w
+            .set_musurements_at(
+                Temperature::fahrenheit(82.0),
+                Humidity::percent(70.0),
+                Pressure::inhg(29.2),
+                at(10),
+            )
+            .is_ok());
+        assert!(w
+            .set_musurements_at(
+                Temperature::fahrenheit(78.0),
+                Humidity::percent(90.0),
+                Pressure::inhg(29.2),
+                at(20),
+            )
+            .is_ok());
+        assert!(w
+            .set_musurements_at(
+                Temperature::fahrenheit(75.0),
+                Humidity::percent(85.0),
+                Pressure::inhg(29.6),
+                at(30),
+            )
+            .is_ok());
+    });
+    let w = weather_data.borrow();
+    // 最旧的那条被挤掉了
+    let temperatures: Vec<f64> = w
+        .history()
+        .iter()
+        .map(|r| r.measurements.temperature.as_fahrenheit())
+        .collect();
+    assert_eq!(temperatures, vec![82.0, 78.0, 75.0]);
+    assert_eq!(w.last_readings(1)[0].at, at(30));
+    assert_eq!(w.last_readings(10).len(), 3);
+    let window = w.readings_between(at(15), at(30));
+    assert_eq!(window.len(), 1);
+    assert_eq!(window[0].measurements.humidity, Humidity::percent(90.0));
+    drop(w);
+
+    // 中途加入的 display 立刻拿到最近两条
+    let late = StatisticsDisplay::new(weather_data.clone());
+    let id = weather_data.borrow().subscription_of(&late).unwrap();
+    let lines =
+        output::capture(|| assert_eq!(weather_data.borrow_mut().replay(id, 2).delivered, 2));
+    assert_eq!(
+        lines.last().unwrap(),
+        "Avg/Max/Min temperature = 76.5/78/75"
+    );
+    assert_eq!(late.borrow().readings(), 2);
+
+    let quiet = WeatherDate::with_history(0);
+    assert!(quiet.history().is_empty());
+}
+
+#[test]
+fn test_units() {
+    use crate::output;
+
+    let t = Temperature::celsius(100.0);
+    assert_eq!(t.as_fahrenheit(), 212.0);
+    assert_eq!(Temperature::fahrenheit(-40.0).as_celsius(), -40.0);
+    assert!((Pressure::hpa(1013.25).as_inhg() - 29.92).abs() < 0.01);
+    assert_eq!(UnitSystem::Metric.pressure(Pressure::inhg(29.92)), 1013.2);
+    assert_eq!(UnitSystem::Imperial.pressure_unit(), "inHg");
+
+    // 同一个主题，不同的 display 各自选单位制
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let _imperial = CurrentConditionsDisplay::new(weather_data.clone());
+    let _metric = CurrentConditionsDisplay::with_units(weather_data.clone(), UnitSystem::Metric);
+    let _statistics = StatisticsDisplay::with_units(weather_data.clone(), UnitSystem::Metric);
+    let _heat_index = HeatIndexDisplay::with_units(weather_data.clone(), UnitSystem::Metric);
+    let lines = output::capture(|| {
+        assert!(weather_data
+            .borrow_mut()
+            .set_musurements(
+                Temperature::celsius(26.5),
+                Humidity::percent(65.0),
+                Pressure::hpa(1030.0),
+            )
+            .is_ok());
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "Current conditions: 79.7 F degrees and 65 % humidity",
+            "Current conditions: 26.5 C degrees and 65 % humidity",
+            "Avg/Max/Min temperature = 26.5/26.5/26.5",
+            "Heat index is 28.08992",
+        ]
+    );
+}
+
+#[test]
+fn test_fallible_observers() {
+    use crate::output;
+
+    /// 湿度超过 100% 就报错，气压是 0 就 panic
+    struct Validator;
+    impl Observer for Validator {
+        fn update(
+            &mut self,
+            _: Temperature,
+            humidity: Humidity,
+            pressure: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            if pressure.as_inhg() == 0.0 {
+                panic!("sensor offline");
+            }
+            if humidity.as_percent() > 100.0 {
+                return Err(format!("humidity {}% out of range", humidity.as_percent()).into());
+            }
+            Ok(())
+        }
+    }
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let validator = weather_data
+        .borrow_mut()
+        .register_observer(Rc::new(RefCell::new(Validator)));
+    // 排在出错的观察者后面也照样收到通知
+    let statistics = StatisticsDisplay::new(weather_data.clone());
+
+    let mut reports = Vec::new();
+    output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        for (h, p) in [(65.0, 30.4), (120.0, 30.4), (65.0, 0.0), (65.0, 30.4)] {
+            reports.push(set(&mut w, 80.0, h, p));
+        }
+    });
+    let (ok, error, panicked) = (&reports[0], &reports[1], &reports[2]);
+    assert!(ok.is_ok());
+    assert_eq!(ok.delivered, 2);
+    assert_eq!(
+        error.failures,
+        vec![(
+            validator,
+            ObserverFailure::Error("humidity 120% out of range".to_string())
+        )]
+    );
+    assert_eq!(error.delivered, 1);
+    assert_eq!(
+        panicked.failures,
+        vec![(
+            validator,
+            ObserverFailure::Panic("sensor offline".to_string())
+        )]
+    );
+    assert_eq!(
+        panicked.failures[0].1.to_string(),
+        "observer panicked: sensor offline"
+    );
+
+    // panic 之后观察者还能继续用
+    assert!(reports[3].is_ok());
+    assert_eq!(reports[3].delivered, 2);
+    assert_eq!(statistics.borrow().readings(), 4);
+}
+
+#[test]
+fn test_priority_and_reentrancy() {
+    use crate::output;
+
+    type Log = Rc<RefCell<Vec<&'static str>>>;
+    struct Named {
+        name: &'static str,
+        log: Log,
+    }
+    impl Observer for Named {
+        fn update(
+            &mut self,
+            _: Temperature,
+            _: Humidity,
+            _: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            self.log.borrow_mut().push(self.name);
+            Ok(())
+        }
+    }
+    /// 第一次收到通知时取消一个观察者、注册一个新的，再取消自己
+    struct Meddler {
+        registry: ObserverRegistry,
+        log: Log,
+        victim: Option<SubscriptionId>,
+        newcomer: Option<Rc<RefCell<dyn Observer>>>,
+        own: Option<SubscriptionId>,
+    }
+    impl Observer for Meddler {
+        fn update(
+            &mut self,
+            _: Temperature,
+            _: Humidity,
+            _: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            self.log.borrow_mut().push("meddler");
+            if let Some(id) = self.victim.take() {
+                self.registry.remove_observer(id);
+            }
+            if let Some(o) = self.newcomer.take() {
+                self.registry.register_observer(o);
+            }
+            if let Some(id) = self.own.take() {
+                self.registry.remove_observer(id);
+            }
+            Ok(())
+        }
+    }
+
+    let log: Log = Rc::new(RefCell::new(Vec::new()));
+    let named = |name| {
+        Rc::new(RefCell::new(Named {
+            name,
+            log: log.clone(),
+        }))
+    };
+    let mut w = WeatherDate::new();
+    let display = w.register_observer(named("display"));
+    let logger = w.register_observer(named("logger"));
+    let alarm = w.register_observer(named("alarm"));
+    let audit = w.register_observer(named("audit"));
+    assert!(w.set_priority(alarm, 10));
+    assert!(w.set_priority(audit, -1));
+    let reading = |w: &mut WeatherDate| {
+        output::capture(|| assert!(set(w, 80.0, 65.0, 30.4).is_ok()));
+        log.replace(Vec::new())
+    };
+    // 报警先收到，同一优先级按注册顺序，负优先级最后
+    assert_eq!(reading(&mut w), vec!["alarm", "display", "logger", "audit"]);
+    assert!(w.set_priority(display, 10));
+    assert!(!w.set_priority(SubscriptionId(99), 1));
+    // 同一优先级里先注册的在前，和设置优先级的先后无关
+    assert_eq!(reading(&mut w), vec!["display", "alarm", "logger", "audit"]);
+    assert!(w.set_priority(alarm, 0));
+    assert_eq!(reading(&mut w), vec!["display", "logger", "alarm", "audit"]);
+
+    let meddler = Rc::new(RefCell::new(Meddler {
+        registry: w.registry(),
+        log: log.clone(),
+        victim: Some(logger),
+        newcomer: Some(named("newcomer")),
+        own: None,
+    }));
+    let id = w.register_observer(meddler.clone());
+    meddler.borrow_mut().own = Some(id);
+    w.set_priority(id, 5);
+    // logger 这一轮就不再收到，newcomer 下一轮才收到
+    assert_eq!(
+        reading(&mut w),
+        vec!["display", "meddler", "alarm", "audit"]
+    );
+    assert_eq!(
+        reading(&mut w),
+        vec!["display", "alarm", "newcomer", "audit"]
+    );
+    assert_eq!(w.observer_count(), 4);
+
+    /// 第一次收到通知时订阅，第二次把订阅守卫 drop 掉
+    struct Subscriber {
+        registry: ObserverRegistry,
+        newcomer: Option<Rc<RefCell<dyn Observer>>>,
+        guard: Option<Subscription>,
+    }
+    impl Observer for Subscriber {
+        fn update(
+            &mut self,
+            _: Temperature,
+            _: Humidity,
+            _: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            match self.newcomer.take() {
+                Some(o) => self.guard = Some(self.registry.subscribe(o)),
+                None => self.guard = None,
+            }
+            Ok(())
+        }
+    }
+    let mut w = WeatherDate::new();
+    let late = StatisticsDisplay::detached(UnitSystem::Imperial);
+    let subscriber = w.register_observer(Rc::new(RefCell::new(Subscriber {
+        registry: w.registry(),
+        newcomer: Some(late.clone()),
+        guard: None,
+    })));
+    // 排在最后，第二轮 late 先收到，然后才取消订阅
+    w.set_priority(subscriber, -1);
+    output::capture(|| {
+        for _ in 0..3 {
+            assert!(set(&mut w, 80.0, 65.0, 30.4).is_ok());
+        }
+    });
+    assert_eq!(late.borrow().readings(), 1);
+    assert_eq!(w.observer_count(), 1);
+}
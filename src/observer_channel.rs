@@ -0,0 +1,145 @@
+//! 跨线程的观察者模式，observer::WeatherDate 基于 Rc/RefCell 只能在一个线程里用
+//! 这里的主题把读数通过 mpsc 通道分发出去，传感器线程 publish，显示线程各自从 Receiver 里收
+//! WeatherStation 可以 clone 到任意线程，订阅和取消订阅也可以在任意线程进行
+//! 接收端被 drop 掉之后，下一次 publish 发送失败就把它清理掉，不会报错
+
+use crate::observer::{Humidity, Measurements, Pressure, SubscriptionId, Temperature};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+struct StationInner {
+    subscribers: Vec<(SubscriptionId, Sender<Measurements>)>,
+    next_id: u64,
+    latest: Option<Measurements>,
+}
+
+/// 线程安全的气象站，clone 出来的实例共享同一份订阅列表
+#[derive(Clone)]
+pub struct WeatherStation {
+    inner: Arc<Mutex<StationInner>>,
+}
+
+impl WeatherStation {
+    pub fn new() -> Self {
+        WeatherStation {
+            inner: Arc::new(Mutex::new(StationInner {
+                subscribers: Vec::new(),
+                next_id: 0,
+                latest: None,
+            })),
+        }
+    }
+
+    /// 订阅之后只收到之后 publish 的读数
+    pub fn subscribe(&self) -> (SubscriptionId, Receiver<Measurements>) {
+        let (tx, rx) = channel();
+        let mut inner = self.inner.lock().unwrap();
+        let id = SubscriptionId(inner.next_id);
+        inner.next_id += 1;
+        inner.subscribers.push((id, tx));
+        (id, rx)
+    }
+
+    /// 取消订阅后接收端会在读完剩下的读数后断开，id 不存在返回 false
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.subscribers.len();
+        inner.subscribers.retain(|(i, _)| *i != id);
+        inner.subscribers.len() != len
+    }
+
+    /// 把读数发给所有订阅者，返回成功送达的数量，接收端已经断开的顺便清理掉
+    pub fn publish(
+        &self,
+        temperature: Temperature,
+        humidity: Humidity,
+        pressure: Pressure,
+    ) -> usize {
+        let m = Measurements {
+            temperature,
+            humidity,
+            pressure,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.latest = Some(m);
+        inner.subscribers.retain(|(_, tx)| tx.send(m).is_ok());
+        inner.subscribers.len()
+    }
+
+    /// 当前的订阅数量，已经断开但还没 publish 过的也算在内
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.lock().unwrap().subscribers.len()
+    }
+
+    /// 最近一次 publish 的读数
+    pub fn latest(&self) -> Option<Measurements> {
+        self.inner.lock().unwrap().latest
+    }
+}
+
+impl Default for WeatherStation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_weather_station() {
+    use std::thread;
+
+    let station = WeatherStation::new();
+    let (_, rx) = station.subscribe();
+    // 在主线程订阅好再把接收端交给显示线程，发布之前两个订阅都已经在了，收到三条就结束
+    let display = {
+        let station = station.clone();
+        let (id, rx) = station.subscribe();
+        thread::spawn(move || {
+            let readings: Vec<Measurements> = rx.iter().take(3).collect();
+            assert!(station.unsubscribe(id));
+            readings
+        })
+    };
+    assert_eq!(station.subscriber_count(), 2);
+    let sensor = {
+        let station = station.clone();
+        thread::spawn(move || {
+            station.publish(
+                Temperature::fahrenheit(80.0),
+                Humidity::percent(65.0),
+                Pressure::inhg(30.4),
+            );
+            station.publish(
+                Temperature::fahrenheit(82.0),
+                Humidity::percent(70.0),
+                Pressure::inhg(29.2),
+            );
+            station.publish(
+                Temperature::fahrenheit(78.0),
+                Humidity::percent(90.0),
+                Pressure::inhg(29.2),
+            );
+        })
+    };
+    sensor.join().unwrap();
+    let readings = display.join().unwrap();
+    assert_eq!(readings.len(), 3);
+    assert_eq!(readings[2].humidity, Humidity::percent(90.0));
+    assert_eq!(rx.try_iter().count(), 3);
+    assert_eq!(
+        station.latest().map(|m| m.temperature.as_fahrenheit()),
+        Some(78.0)
+    );
+
+    // 接收端 drop 掉之后下一次 publish 就被清理
+    drop(rx);
+    assert_eq!(station.subscriber_count(), 1);
+    assert_eq!(
+        station.publish(
+            Temperature::fahrenheit(75.0),
+            Humidity::percent(85.0),
+            Pressure::inhg(29.6)
+        ),
+        0
+    );
+    assert_eq!(station.subscriber_count(), 0);
+}
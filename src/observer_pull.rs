@@ -1,44 +1,88 @@
 //! 观察者订阅某个主题，主题对象管理某些数据，一旦主题内的数据改变，就会通知观察者或者把行数据送到观察者手上
-//! 
+//!
 //! 拉取模式与推模式不同的地方在于：主题每个数据都有 getter，观察者在自己的 update 里面选择获取哪些数据，
 //! 主题调用观察者的 update 时也会把自己当作参数传过去，这样观察者就能知道是哪个主题产生了变化，要拉取哪些数据
-//! 
-//! unimplemented!()
-
-pub trait Subject<T>
-where
-    T: Observer,
-{
-    fn register_observer(&mut self, o: std::rc::Rc<std::cell::RefCell<T>>);
-    fn remove_observer(
+//! 同时传过去的还有一个 Changes，标明和上一次通知相比哪些数据变了，不关心的数据没变就可以什么都不做
+//! 每个主题都有唯一的 id 和名字，一个观察者可以订阅好几个气象站，靠 id 区分是哪个站的数据
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_STATION_ID: AtomicU64 = AtomicU64::new(0);
+
+/// 气象站的 id，进程内唯一，名字可以重复但 id 不会
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StationId(u64);
+
+pub trait Subject {
+    fn register_observer(&mut self, o: Rc<RefCell<dyn Observer>>);
+    /// 按 Rc 的指向判断是不是同一个观察者，具体类型和 dyn Observer 的 Rc 都可以
+    fn remove_observer<O: Observer + ?Sized>(
         &mut self,
-        o: std::rc::Rc<std::cell::RefCell<T>>,
-    ) -> Option<std::rc::Rc<std::cell::RefCell<T>>>;
+        o: &Rc<RefCell<O>>,
+    ) -> Option<Rc<RefCell<dyn Observer>>>;
     fn notify_observers(&mut self);
 }
 
-pub struct WeatherDate<T: Observer> {
+/// 和上一次通知相比哪些数据变了，观察者注册之后收到的第一次通知全部算作变了
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Changes {
+    pub temperature: bool,
+    pub humidity: bool,
+    pub pressure: bool,
+}
+
+impl Changes {
+    pub fn all() -> Self {
+        Changes {
+            temperature: true,
+            humidity: true,
+            pressure: true,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        !(self.temperature || self.humidity || self.pressure)
+    }
+}
+
+pub struct WeatherDate {
+    id: StationId,
+    name: String,
     // 不能用 HashSet，因为它不能 for i in &mut hashset
-    // 存的也是RecCell<T>不能是T
-    observers: Vec<std::rc::Rc<std::cell::RefCell<T>>>,
+    // 存的是 trait object，这样不同类型的观察者可以注册到同一个主题上
+    observers: Vec<Registered>,
     temperature: f64,
     humidity: f64,
     pressure: f64,
+    // 上一次通知出去的温度、湿度、气压
+    last_notified: Option<(f64, f64, f64)>,
 }
 
-impl<O> WeatherDate<O>
-where
-    O: Observer,
-{
+impl WeatherDate {
     pub fn new() -> Self {
+        WeatherDate::named("weather station")
+    }
+    pub fn named(name: &str) -> Self {
         WeatherDate {
+            id: StationId(NEXT_STATION_ID.fetch_add(1, Ordering::Relaxed)),
+            name: name.to_string(),
             observers: Vec::new(),
             temperature: 0.0,
             humidity: 0.0,
             pressure: 0.0,
+            last_notified: None,
         }
     }
 
+    pub fn id(&self) -> StationId {
+        self.id
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn temperature(&self) -> f64 {
         self.temperature
     }
@@ -53,68 +97,106 @@ where
         self.temperature = temprature;
         self.humidity = humidity;
         self.pressure = pressure;
-        // 不能调用 trait 里面的方法去通知
+        self.measurements_changed();
+    }
+
+    pub fn measurements_changed(&mut self) {
+        self.notify_observers();
+    }
+
+    /// 和上一次通知出去的数据比较，还没通知过就全部算作变了
+    pub fn changes(&self) -> Changes {
+        match self.last_notified {
+            None => Changes::all(),
+            Some((t, h, p)) => Changes {
+                temperature: t != self.temperature,
+                humidity: h != self.humidity,
+                pressure: p != self.pressure,
+            },
+        }
+    }
+}
+
+/// 注册的观察者，记着它是不是已经收到过通知
+/// Changes 是和主题上一次通知比的，后注册的观察者没见过那次的数据，所以它的第一次通知要全部算作变了
+struct Registered {
+    observer: Rc<RefCell<dyn Observer>>,
+    notified: Cell<bool>,
+}
+
+impl Default for WeatherDate {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl<T> Subject<T> for WeatherDate<T>
-where
-    T: Observer + std::cmp::PartialEq,
-{
-    fn register_observer(&mut self, o: std::rc::Rc<std::cell::RefCell<T>>) {
-        self.observers.push(o);
+impl Subject for WeatherDate {
+    fn register_observer(&mut self, o: Rc<RefCell<dyn Observer>>) {
+        self.observers.push(Registered {
+            observer: o,
+            notified: Cell::new(false),
+        });
     }
-    fn remove_observer(
+    fn remove_observer<O: Observer + ?Sized>(
         &mut self,
-        o: std::rc::Rc<std::cell::RefCell<T>>,
-    ) -> Option<std::rc::Rc<std::cell::RefCell<T>>> {
-        self.observers.remove_item(&o)
+        o: &Rc<RefCell<O>>,
+    ) -> Option<Rc<RefCell<dyn Observer>>> {
+        // 只比较数据地址
+        let target = Rc::as_ptr(o) as *const ();
+        let i = self
+            .observers
+            .iter()
+            .position(|x| Rc::as_ptr(&x.observer) as *const () == target)?;
+        Some(self.observers.remove(i).observer)
     }
+    /// 每次都会通知所有观察者，数据由观察者自己通过 getter 拉取
+    /// Changes 标明哪些数据变了，没有数据变的时候是空的，观察者可以什么都不做
     fn notify_observers(&mut self) {
+        let changes = self.changes();
+        self.last_notified = Some((self.temperature, self.humidity, self.pressure));
         for i in &self.observers {
-            i.borrow_mut()
-                .update(Box::new(self));
+            let changes = if i.notified.replace(true) {
+                changes
+            } else {
+                Changes::all()
+            };
+            i.observer.borrow_mut().update(self, changes);
         }
     }
 }
 
 pub trait Observer {
-    fn update(&mut self, sub: Box<dyn Subject<T>>);
+    fn update(&mut self, subject: &WeatherDate, changes: Changes);
 }
 
-/// 观察者
+/// 观察者，只拉取温度和湿度
 pub struct CurrentConditionsDisplay {
     temperature: f64,
     humidity: f64,
-    weather_data: std::rc::Weak<std::cell::RefCell<WeatherDate<Self>>>,
-}
-impl PartialEq for CurrentConditionsDisplay {
-    fn eq(&self, other: &Self) -> bool {
-        self.temperature == other.temperature && self.humidity == other.humidity
-    }
 }
 
 impl CurrentConditionsDisplay {
-    pub fn new(
-        weather_data: std::rc::Rc<std::cell::RefCell<WeatherDate<Self>>>,
-    ) -> std::rc::Rc<std::cell::RefCell<Self>> {
-        // 这里有循环引用，一对多，一里面存的是多的 Rc，多存的是一的 Weak
+    pub fn new(weather_data: Rc<RefCell<WeatherDate>>) -> Rc<RefCell<Self>> {
+        // 主题每次都会把自己传过来，所以不需要再存一份主题的引用
         let s = CurrentConditionsDisplay {
             temperature: 0.0,
             humidity: 0.0,
-            weather_data: std::rc::Rc::downgrade(&weather_data),
         };
-        let p = std::rc::Rc::new(std::cell::RefCell::new(s));
+        let p = Rc::new(RefCell::new(s));
         weather_data.borrow_mut().register_observer(p.clone());
-        return p;
+        p
     }
 }
 
 impl Observer for CurrentConditionsDisplay {
-    fn update(&mut self, temperature: f64, humidity: f64, _: f64) {
-        self.temperature = temperature;
-        self.humidity = humidity;
-        println!("{}", self)
+    fn update(&mut self, subject: &WeatherDate, changes: Changes) {
+        // 只有气压变了的话显示的内容不变，不用重新显示
+        if !(changes.temperature || changes.humidity) {
+            return;
+        }
+        self.temperature = subject.temperature();
+        self.humidity = subject.humidity();
+        outputln!("{}", self)
     }
 }
 impl std::fmt::Display for CurrentConditionsDisplay {
@@ -126,3 +208,274 @@ impl std::fmt::Display for CurrentConditionsDisplay {
         )
     }
 }
+
+/// 区域平均，同时订阅好几个气象站，每个站只算它最近的一次读数
+pub struct RegionalAverageDisplay {
+    // 按 id 排，输出的顺序稳定
+    stations: BTreeMap<StationId, StationReading>,
+}
+
+struct StationReading {
+    name: String,
+    temperature: f64,
+    humidity: f64,
+}
+
+impl RegionalAverageDisplay {
+    pub fn new(stations: &[Rc<RefCell<WeatherDate>>]) -> Rc<RefCell<Self>> {
+        let p = Rc::new(RefCell::new(RegionalAverageDisplay {
+            stations: BTreeMap::new(),
+        }));
+        for weather_data in stations {
+            weather_data.borrow_mut().register_observer(p.clone());
+        }
+        p
+    }
+
+    /// 已经报过数据的气象站数量
+    pub fn station_count(&self) -> usize {
+        self.stations.len()
+    }
+    pub fn temperature_at(&self, id: StationId) -> Option<f64> {
+        self.stations.get(&id).map(|r| r.temperature)
+    }
+    pub fn station_names(&self) -> Vec<&str> {
+        self.stations.values().map(|r| r.name.as_str()).collect()
+    }
+
+    /// 还没有气象站报过数据时返回 None
+    pub fn average_temperature(&self) -> Option<f64> {
+        self.average(|r| r.temperature)
+    }
+    pub fn average_humidity(&self) -> Option<f64> {
+        self.average(|r| r.humidity)
+    }
+
+    fn average<F: Fn(&StationReading) -> f64>(&self, f: F) -> Option<f64> {
+        if self.stations.is_empty() {
+            None
+        } else {
+            Some(self.stations.values().map(f).sum::<f64>() / self.stations.len() as f64)
+        }
+    }
+}
+
+impl Observer for RegionalAverageDisplay {
+    fn update(&mut self, subject: &WeatherDate, changes: Changes) {
+        // 从某个站收到的第一次通知 Changes 是全部，所以订阅之前就报过数据的站也会记下来
+        if !(changes.temperature || changes.humidity) {
+            return;
+        }
+        self.stations.insert(
+            subject.id(),
+            StationReading {
+                name: subject.name().to_string(),
+                temperature: subject.temperature(),
+                humidity: subject.humidity(),
+            },
+        );
+        outputln!("{}", self)
+    }
+}
+impl std::fmt::Display for RegionalAverageDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.average_temperature(), self.average_humidity()) {
+            (Some(t), Some(h)) => write!(
+                f,
+                "Regional average: {} F degrees and {} % humidity over {} stations",
+                t,
+                h,
+                self.station_count()
+            ),
+            _ => write!(f, "Regional average: no data"),
+        }
+    }
+}
+
+#[test]
+fn test_pull_observer() {
+    use crate::output;
+
+    /// 另一个观察者只关心气压，别的数据不去拉
+    struct PressureLog {
+        pressures: Vec<f64>,
+    }
+    impl Observer for PressureLog {
+        fn update(&mut self, subject: &WeatherDate, _: Changes) {
+            self.pressures.push(subject.pressure());
+        }
+    }
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let current = CurrentConditionsDisplay::new(weather_data.clone());
+    let log = Rc::new(RefCell::new(PressureLog {
+        pressures: Vec::new(),
+    }));
+    weather_data.borrow_mut().register_observer(log.clone());
+
+    let lines = output::capture(|| {
+        weather_data.borrow_mut().set_musurements(80.0, 65.0, 30.4);
+        weather_data.borrow_mut().set_musurements(82.0, 70.0, 29.2);
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "Current conditions: 80 F degrees and 65 % humidity",
+            "Current conditions: 82 F degrees and 70 % humidity",
+        ]
+    );
+    assert_eq!(log.borrow().pressures, vec![30.4, 29.2]);
+
+    // 按 Rc 的指向删除，同类型的另一个 display 不受影响
+    let other = CurrentConditionsDisplay::new(weather_data.clone());
+    assert!(weather_data
+        .borrow_mut()
+        .remove_observer(&current)
+        .is_some());
+    assert!(weather_data
+        .borrow_mut()
+        .remove_observer(&current)
+        .is_none());
+    let lines = output::capture(|| weather_data.borrow_mut().set_musurements(78.0, 90.0, 29.2));
+    assert_eq!(
+        lines,
+        vec!["Current conditions: 78 F degrees and 90 % humidity"]
+    );
+    assert_eq!(other.borrow().temperature, 78.0);
+    assert_eq!(log.borrow().pressures, vec![30.4, 29.2, 29.2]);
+}
+
+#[test]
+fn test_change_set() {
+    use crate::output;
+
+    /// 把每次收到的 Changes 记下来
+    struct ChangeLog {
+        changes: Vec<Changes>,
+    }
+    impl Observer for ChangeLog {
+        fn update(&mut self, _: &WeatherDate, changes: Changes) {
+            self.changes.push(changes);
+        }
+    }
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let _current = CurrentConditionsDisplay::new(weather_data.clone());
+    let log = Rc::new(RefCell::new(ChangeLog {
+        changes: Vec::new(),
+    }));
+    weather_data.borrow_mut().register_observer(log.clone());
+
+    let lines = output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        w.set_musurements(80.0, 65.0, 30.4);
+        // 只有气压变了，温湿度显示不用刷新
+        w.set_musurements(80.0, 65.0, 29.2);
+        w.set_musurements(82.0, 65.0, 29.2);
+        w.set_musurements(82.0, 65.0, 29.2);
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "Current conditions: 80 F degrees and 65 % humidity",
+            "Current conditions: 82 F degrees and 65 % humidity",
+        ]
+    );
+    let only = |temperature, humidity, pressure| Changes {
+        temperature,
+        humidity,
+        pressure,
+    };
+    assert_eq!(
+        log.borrow().changes,
+        vec![
+            Changes::all(),
+            only(false, false, true),
+            only(true, false, false),
+            Changes::default(),
+        ]
+    );
+    assert!(log.borrow().changes[3].is_empty());
+    assert!(weather_data.borrow().changes().is_empty());
+
+    // 后注册的观察者没见过之前的数据，第一次收到的是全部，之后才按差异来
+    let late = Rc::new(RefCell::new(ChangeLog {
+        changes: Vec::new(),
+    }));
+    weather_data.borrow_mut().register_observer(late.clone());
+    output::capture(|| {
+        let mut w = weather_data.borrow_mut();
+        w.set_musurements(82.0, 65.0, 30.0);
+        w.set_musurements(82.0, 65.0, 30.0);
+    });
+    assert_eq!(
+        late.borrow().changes,
+        vec![Changes::all(), Changes::default()]
+    );
+    assert_eq!(log.borrow().changes[4], only(false, false, true));
+}
+
+#[test]
+fn test_regional_average() {
+    use crate::output;
+
+    let north = Rc::new(RefCell::new(WeatherDate::named("north")));
+    let south = Rc::new(RefCell::new(WeatherDate::named("south")));
+    let coast = Rc::new(RefCell::new(WeatherDate::named("coast")));
+    assert_ne!(north.borrow().id(), south.borrow().id());
+    assert_eq!(coast.borrow().name(), "coast");
+
+    let region = RegionalAverageDisplay::new(&[north.clone(), south.clone(), coast.clone()]);
+    assert_eq!(region.borrow().average_temperature(), None);
+    assert_eq!(region.borrow().to_string(), "Regional average: no data");
+
+    let lines = output::capture(|| {
+        north.borrow_mut().set_musurements(70.0, 60.0, 30.1);
+        south.borrow_mut().set_musurements(86.0, 70.0, 29.9);
+        coast.borrow_mut().set_musurements(78.0, 80.0, 29.8);
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "Regional average: 70 F degrees and 60 % humidity over 1 stations",
+            "Regional average: 78 F degrees and 65 % humidity over 2 stations",
+            "Regional average: 78 F degrees and 70 % humidity over 3 stations",
+        ]
+    );
+    assert_eq!(
+        region.borrow().station_names(),
+        vec!["north", "south", "coast"]
+    );
+
+    // 同一个站的新读数替换掉它旧的那个，不会重复计算
+    output::capture(|| north.borrow_mut().set_musurements(73.0, 60.0, 30.1));
+    let r = region.borrow();
+    assert_eq!(r.station_count(), 3);
+    assert_eq!(r.temperature_at(north.borrow().id()), Some(73.0));
+    assert_eq!(r.temperature_at(south.borrow().id()), Some(86.0));
+    assert_eq!(r.average_temperature(), Some(79.0));
+    assert_eq!(r.average_humidity(), Some(70.0));
+    drop(r);
+
+    // 从一个站取消订阅之后只是不再更新那个站的读数
+    assert!(coast.borrow_mut().remove_observer(&region).is_some());
+    let lines = output::capture(|| coast.borrow_mut().set_musurements(60.0, 90.0, 29.5));
+    assert!(lines.is_empty());
+    assert_eq!(
+        region.borrow().temperature_at(coast.borrow().id()),
+        Some(78.0)
+    );
+
+    // 气象站先报过数据，之后才订阅，下一次即使只有气压变了也要把站算进去
+    let late = RegionalAverageDisplay::new(&[north.clone(), south.clone()]);
+    let lines = output::capture(|| {
+        north.borrow_mut().set_musurements(73.0, 60.0, 29.9);
+        south.borrow_mut().set_musurements(86.0, 70.0, 30.0);
+    });
+    assert_eq!(late.borrow().station_count(), 2);
+    assert_eq!(late.borrow().average_temperature(), Some(79.5));
+    assert_eq!(
+        lines.last().unwrap(),
+        "Regional average: 79.5 F degrees and 65 % humidity over 2 stations"
+    );
+}
@@ -0,0 +1,325 @@
+//! 从记录下来的气象站日志回放读数，用来给 WeatherDate 做回归测试
+//! 日志是 CSV，每行 `timestamp,temperature,humidity,pressure`，timestamp 是 Unix 秒数，可以带小数
+//! 温度是华氏度，湿度是百分比，气压是英寸汞柱
+//! 第一个不是注释的行可以是表头，空行和 # 开头的注释行跳过，文件开头的 BOM 也会去掉
+//! 格式不对的行不会中断解析，带着行号收集起来，方便一次看到所有问题
+
+use crate::observer::{
+    Humidity, Measurements, NotificationReport, Pressure, Temperature, WeatherDate,
+};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// 日志里的一条读数，line 从 1 开始
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogRecord {
+    pub line: usize,
+    pub at: SystemTime,
+    pub measurements: Measurements,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeatherLogErr {
+    FieldCount {
+        line: usize,
+        found: usize,
+    },
+    InvalidValue {
+        line: usize,
+        field: &'static str,
+        value: String,
+    },
+    /// 时间戳比前一条还早，回放的时候没法倒着等
+    OutOfOrder {
+        line: usize,
+    },
+}
+impl WeatherLogErr {
+    pub fn line(&self) -> usize {
+        match self {
+            WeatherLogErr::FieldCount { line, .. }
+            | WeatherLogErr::InvalidValue { line, .. }
+            | WeatherLogErr::OutOfOrder { line } => *line,
+        }
+    }
+}
+impl Display for WeatherLogErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            WeatherLogErr::FieldCount { line, found } => {
+                write!(f, "line {}: expected 4 fields, found {}", line, found)
+            }
+            WeatherLogErr::InvalidValue { line, field, value } => {
+                write!(f, "line {}: invalid {} `{}`", line, field, value)
+            }
+            WeatherLogErr::OutOfOrder { line } => {
+                write!(
+                    f,
+                    "line {}: timestamp is earlier than the previous line",
+                    line
+                )
+            }
+        }
+    }
+}
+impl Error for WeatherLogErr {}
+
+const FIELDS: [&str; 4] = ["timestamp", "temperature", "humidity", "pressure"];
+
+/// 回放速度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// 按记录的时间间隔等待
+    RealTime,
+    /// 时间间隔除以这个倍数，倍数不是大于 0 的有限数（0、负数、NaN、无穷大）时当作 Instant
+    Accelerated(f64),
+    /// 不等待
+    Instant,
+}
+
+/// 解析好的日志，正常的读数和出错的行分开放
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WeatherLog {
+    pub records: Vec<LogRecord>,
+    pub errors: Vec<WeatherLogErr>,
+}
+
+impl WeatherLog {
+    pub fn parse(text: &str) -> Self {
+        let mut log = WeatherLog::default();
+        for (i, line) in text.lines().enumerate() {
+            log.push_line(i + 1, line);
+        }
+        log
+    }
+
+    /// 从文件之类的地方按行读，只有 IO 出错才返回 Err
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut log = WeatherLog::default();
+        for (i, line) in reader.lines().enumerate() {
+            log.push_line(i + 1, &line?);
+        }
+        Ok(log)
+    }
+
+    fn push_line(&mut self, line: usize, text: &str) {
+        let text = if line == 1 {
+            text.trim_start_matches('\u{feff}')
+        } else {
+            text
+        };
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            return;
+        }
+        // 前面还没有读数也没有出错的行，说明这是第一个不是注释的行
+        let first = self.records.is_empty() && self.errors.is_empty();
+        if first && text.starts_with(FIELDS[0]) {
+            return;
+        }
+        match parse_record(line, text) {
+            Ok(r) => {
+                if let Some(last) = self.records.last() {
+                    if r.at < last.at {
+                        self.errors.push(WeatherLogErr::OutOfOrder { line });
+                        return;
+                    }
+                }
+                self.records.push(r);
+            }
+            Err(e) => self.errors.push(e),
+        }
+    }
+
+    /// 按顺序把读数送进主题，每条都用记录里的时间戳
+    /// 返回每条读数所在的行号和那一轮通知的结果，观察者出错不会中断回放
+    pub fn replay(
+        &self,
+        weather_data: &mut WeatherDate,
+        speed: ReplaySpeed,
+    ) -> Vec<(usize, NotificationReport)> {
+        let mut reports = Vec::with_capacity(self.records.len());
+        let mut previous: Option<SystemTime> = None;
+        for r in &self.records {
+            if let Some(previous) = previous {
+                let gap = r.at.duration_since(previous).unwrap_or_default();
+                match speed {
+                    ReplaySpeed::RealTime => thread::sleep(gap),
+                    ReplaySpeed::Accelerated(factor) => thread::sleep(scale(gap, factor)),
+                    ReplaySpeed::Instant => {}
+                }
+            }
+            previous = Some(r.at);
+            let m = &r.measurements;
+            let report =
+                weather_data.set_musurements_at(m.temperature, m.humidity, m.pressure, r.at);
+            reports.push((r.line, report));
+        }
+        reports
+    }
+}
+
+/// 回放过程中不能 panic，倍数不合法就不等待，倍数太小算出来的时间溢出就等最长的时间
+fn scale(gap: Duration, factor: f64) -> Duration {
+    if !(factor.is_finite() && factor > 0.0) {
+        return Duration::ZERO;
+    }
+    Duration::try_from_secs_f64(gap.as_secs_f64() / factor).unwrap_or(Duration::MAX)
+}
+
+fn parse_record(line: usize, text: &str) -> Result<LogRecord, WeatherLogErr> {
+    let fields: Vec<&str> = text.split(',').map(|f| f.trim()).collect();
+    if fields.len() != FIELDS.len() {
+        return Err(WeatherLogErr::FieldCount {
+            line,
+            found: fields.len(),
+        });
+    }
+    let mut values = [0.0; 4];
+    for (i, (field, value)) in FIELDS.iter().zip(&fields).enumerate() {
+        values[i] = value
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| WeatherLogErr::InvalidValue {
+                line,
+                field,
+                value: value.to_string(),
+            })?;
+    }
+    let at = Duration::try_from_secs_f64(values[0])
+        .map(|d| SystemTime::UNIX_EPOCH + d)
+        .map_err(|_| WeatherLogErr::InvalidValue {
+            line,
+            field: FIELDS[0],
+            value: fields[0].to_string(),
+        })?;
+    Ok(LogRecord {
+        line,
+        at,
+        measurements: Measurements {
+            temperature: Temperature::fahrenheit(values[1]),
+            humidity: Humidity::percent(values[2]),
+            pressure: Pressure::inhg(values[3]),
+        },
+    })
+}
+
+#[test]
+fn test_weather_log() {
+    use crate::observer::{Observer, StatisticsDisplay, Subject};
+    use crate::output;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    let text = "timestamp,temperature,humidity,pressure
+1000000,80,65,30.4
+# 传感器重启
+1000600,82,70
+1000600.5,82,70,29.2
+
+1001200,hot,90,29.2
+999999,78,90,29.2
+1001800,78,90,29.2
+";
+    let log = WeatherLog::read(text.as_bytes()).unwrap();
+    assert_eq!(log, WeatherLog::parse(text));
+    assert_eq!(log.records.len(), 3);
+    assert_eq!(log.records[1].line, 5);
+    let errors: Vec<String> = log.errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "line 4: expected 4 fields, found 3",
+            "line 7: invalid temperature `hot`",
+            "line 8: timestamp is earlier than the previous line",
+        ]
+    );
+
+    // 带 BOM、表头前面有注释的日志，表头不算出错的行；读数后面再出现的表头才算
+    let bom_text = "\u{feff}# exported from station 7\n\ntimestamp,temperature,humidity,pressure\n1000000,80,65,30.4\ntimestamp,temperature,humidity,pressure\n";
+    let exported = WeatherLog::read(bom_text.as_bytes()).unwrap();
+    assert_eq!(exported.records.len(), 1);
+    assert_eq!(exported.records[0].line, 4);
+    assert_eq!(
+        exported.errors,
+        vec![WeatherLogErr::InvalidValue {
+            line: 5,
+            field: "timestamp",
+            value: "timestamp".to_string(),
+        }]
+    );
+
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    let statistics = StatisticsDisplay::new(weather_data.clone());
+    let started = Instant::now();
+    output::capture(|| {
+        // 1800 秒的日志加速一百万倍
+        let reports = log.replay(
+            &mut weather_data.borrow_mut(),
+            ReplaySpeed::Accelerated(1e6),
+        );
+        let lines: Vec<usize> = reports.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![2, 5, 9]);
+        assert!(reports.iter().all(|(_, r)| r.is_ok() && r.delivered == 1));
+    });
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(statistics.borrow().readings(), 3);
+
+    // 不合法的倍数当作 Instant，不会 panic 也不会等待
+    let started = Instant::now();
+    output::capture(|| {
+        for factor in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+            let reports = log.replay(
+                &mut weather_data.borrow_mut(),
+                ReplaySpeed::Accelerated(factor),
+            );
+            assert_eq!(reports.len(), 3);
+        }
+    });
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(statistics.borrow().readings(), 15);
+    assert_eq!(scale(Duration::from_secs(600), 1e-300), Duration::MAX);
+    assert_eq!(
+        scale(Duration::from_secs(600), 2.0),
+        Duration::from_secs(300)
+    );
+    let history = weather_data.borrow().history();
+    assert_eq!(history[2].at, log.records[2].at);
+    assert_eq!(history[2].measurements.temperature.as_fahrenheit(), 78.0);
+
+    // 观察者出错不会中断回放，从结果里能看到是哪一行出的问题
+    struct Humid;
+    impl Observer for Humid {
+        fn update(
+            &mut self,
+            _: Temperature,
+            h: Humidity,
+            _: Pressure,
+        ) -> Result<(), Box<dyn Error>> {
+            if h.as_percent() >= 90.0 {
+                return Err("too humid".into());
+            }
+            Ok(())
+        }
+    }
+    let weather_data = Rc::new(RefCell::new(WeatherDate::new()));
+    weather_data
+        .borrow_mut()
+        .register_observer(Rc::new(RefCell::new(Humid)));
+    let reports = log.replay(&mut weather_data.borrow_mut(), ReplaySpeed::Instant);
+    let failed: Vec<usize> = reports
+        .iter()
+        .filter(|(_, r)| !r.is_ok())
+        .map(|(line, _)| *line)
+        .collect();
+    assert_eq!(failed, vec![9]);
+    assert_eq!(
+        reports[2].1.failures[0].1.to_string(),
+        "observer returned an error: too humid"
+    );
+}
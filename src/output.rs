@@ -0,0 +1,238 @@
+//! 输出抽象，所有模式模块的演示输出都经过这里，而不是直接 println!
+//! 默认写到标准输出，set_sink 可以把整个进程的输出换到别的地方，比如接到自己的日志系统里
+//! 测试里用 capture 把当前线程的输出临时收集起来再断言，这个临时的替换只对当前线程有效，
+//! 这样并行跑的测试不会互相串输出
+//! 错误提示走 eoutputln!，Sink 可以把它和普通输出分开处理
+
+use crate::lazy_static::lazy_static;
+use std::cell::{Cell, RefCell};
+use std::sync::{Arc, Mutex};
+
+/// 输出目标抽象
+/// 写进程的 Sink 时会拿着一把全局锁，实现里如果又用了 outputln!（比如转给的日志系统自己也输出），
+/// 这些输出不会再进这个 Sink，而是直接写到标准输出或标准错误，这样不会自己等自己的锁
+pub trait Sink {
+    fn write_line(&mut self, line: &str);
+    /// 错误提示，默认和普通输出一样处理
+    fn write_error_line(&mut self, line: &str) {
+        self.write_line(line);
+    }
+}
+
+/// 默认输出到标准输出，错误提示输出到标准错误
+pub struct StdoutSink;
+impl Sink for StdoutSink {
+    fn write_line(&mut self, line: &str) {
+        println!("{}", line);
+    }
+    fn write_error_line(&mut self, line: &str) {
+        eprintln!("{}", line);
+    }
+}
+
+/// 把输出收集在内存里，clone 出来的实例共享同一份内容，可以在线程之间传递
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    lines: Arc<Mutex<Vec<String>>>,
+    error_lines: Arc<Mutex<Vec<String>>>,
+}
+impl MemorySink {
+    pub fn new() -> Self {
+        MemorySink::default()
+    }
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+    pub fn error_lines(&self) -> Vec<String> {
+        self.error_lines.lock().unwrap().clone()
+    }
+    pub fn clear(&self) {
+        self.lines.lock().unwrap().clear();
+        self.error_lines.lock().unwrap().clear();
+    }
+}
+impl Sink for MemorySink {
+    fn write_line(&mut self, line: &str) {
+        // 多行内容按行拆开，方便逐行断言
+        self.lines
+            .lock()
+            .unwrap()
+            .extend(line.split('\n').map(|l| l.to_string()));
+    }
+    fn write_error_line(&mut self, line: &str) {
+        self.error_lines
+            .lock()
+            .unwrap()
+            .extend(line.split('\n').map(|l| l.to_string()));
+    }
+}
+
+lazy_static! {
+    static ref SINK: Mutex<Box<dyn Sink + Send>> = Mutex::new(Box::new(StdoutSink));
+}
+
+thread_local! {
+    // capture 期间当前线程的输出先写到这里
+    static CAPTURE: RefCell<Option<Box<dyn Sink>>> = RefCell::new(None);
+    // 当前线程正在调用进程的 Sink
+    static IN_SINK: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 替换整个进程的 Sink，返回之前的那个，所有线程的输出都会写到新的 Sink
+/// 正在 capture 的线程例外，capture 结束之后才会写到这里
+pub fn set_sink(sink: Box<dyn Sink + Send>) -> Box<dyn Sink + Send> {
+    // 某个线程在写输出的时候 panic 了也不影响别的线程继续输出
+    let mut s = SINK.lock().unwrap_or_else(|e| e.into_inner());
+    std::mem::replace(&mut *s, sink)
+}
+
+pub fn write_line(line: &str) {
+    write(line, false);
+}
+
+pub fn write_error_line(line: &str) {
+    write(line, true);
+}
+
+fn write(line: &str, error: bool) {
+    let captured = CAPTURE.with(|c| match c.borrow_mut().as_mut() {
+        Some(sink) => {
+            write_to(&mut **sink, line, error);
+            true
+        }
+        None => false,
+    });
+    if captured {
+        return;
+    }
+    if IN_SINK.with(|c| c.get()) {
+        // Sink 里面又输出了，锁还在自己手上，只能绕开它
+        write_to(&mut StdoutSink, line, error);
+        return;
+    }
+    let _guard = InSinkGuard::enter();
+    let mut sink = SINK.lock().unwrap_or_else(|e| e.into_inner());
+    write_to(&mut **sink, line, error);
+}
+
+fn write_to(sink: &mut dyn Sink, line: &str, error: bool) {
+    if error {
+        sink.write_error_line(line);
+    } else {
+        sink.write_line(line);
+    }
+}
+
+/// Sink panic 了也要把 IN_SINK 复原
+struct InSinkGuard;
+impl InSinkGuard {
+    fn enter() -> Self {
+        IN_SINK.with(|c| c.set(true));
+        InSinkGuard
+    }
+}
+impl Drop for InSinkGuard {
+    fn drop(&mut self) {
+        IN_SINK.with(|c| c.set(false));
+    }
+}
+
+/// drop 的时候恢复 capture 之前的状态，f panic 了也一样
+struct CaptureGuard {
+    previous: Option<Box<dyn Sink>>,
+}
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CAPTURE.with(|c| *c.borrow_mut() = previous);
+    }
+}
+
+/// 在 f 执行期间把当前线程的普通输出收集起来，结束后恢复原来的输出，可以嵌套
+/// 其它线程的输出不受影响
+pub fn capture<F: FnOnce()>(f: F) -> Vec<String> {
+    let sink = MemorySink::new();
+    let previous = CAPTURE.with(|c| c.replace(Some(Box::new(sink.clone()))));
+    let guard = CaptureGuard { previous };
+    f();
+    drop(guard);
+    sink.lines()
+}
+
+/// 和 println! 用法一样，只是写到当前的 Sink
+#[macro_export]
+macro_rules! outputln {
+    () => {
+        $crate::output::write_line("")
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write_line(&format!($($arg)*))
+    };
+}
+
+/// 和 eprintln! 用法一样，写到当前 Sink 的错误输出
+#[macro_export]
+macro_rules! eoutputln {
+    ($($arg:tt)*) => {
+        $crate::output::write_error_line(&format!($($arg)*))
+    };
+}
+
+#[test]
+fn test_memory_sink() {
+    let lines = capture(|| {
+        outputln!("Quack");
+        outputln!("{} {}", 1, 2);
+        eoutputln!("not captured as a line");
+    });
+    assert_eq!(lines, vec!["Quack", "1 2"]);
+
+    let sink = MemorySink::new();
+    let mut s = sink.clone();
+    s.write_line("a\nb");
+    s.write_error_line("oops");
+    assert_eq!(sink.lines(), vec!["a", "b"]);
+    assert_eq!(sink.error_lines(), vec!["oops"]);
+    sink.clear();
+    assert!(sink.lines().is_empty());
+
+    // f panic 了之后 capture 也会撤掉，后面的输出不会再被收集到那个 MemorySink 里
+    let result = std::panic::catch_unwind(|| {
+        capture(|| {
+            outputln!("before");
+            panic!("boom");
+        })
+    });
+    assert!(result.is_err());
+    assert!(CAPTURE.with(|c| c.borrow().is_none()));
+
+    // 别的线程的输出写到进程的 Sink 里，其它测试也可能同时在输出，所以只检查包含
+    let sink = MemorySink::new();
+    let previous = set_sink(Box::new(sink.clone()));
+    std::thread::spawn(|| {
+        outputln!("from another thread");
+        eoutputln!("menu is full");
+    })
+    .join()
+    .unwrap();
+    set_sink(previous);
+    assert!(sink.lines().contains(&"from another thread".to_string()));
+    assert!(sink.error_lines().contains(&"menu is full".to_string()));
+
+    // 转给日志系统的 Sink 自己也用 outputln!，不会死锁
+    struct Logger(MemorySink);
+    impl Sink for Logger {
+        fn write_line(&mut self, line: &str) {
+            self.0.write_line(line);
+            outputln!("logged: {}", line);
+        }
+    }
+    let sink = MemorySink::new();
+    let previous = set_sink(Box::new(Logger(sink.clone())));
+    std::thread::spawn(|| outputln!("to the logger"))
+        .join()
+        .unwrap();
+    set_sink(previous);
+    assert!(sink.lines().contains(&"to the logger".to_string()));
+    assert!(!sink.lines().iter().any(|l| l.starts_with("logged: ")));
+}
@@ -0,0 +1,200 @@
+//! 池塘模拟，建立在策略模式的 Duck 之上，按离散的时钟推进一群鸭子的位置、高度和体力
+//! 每个 tick 鸭子按当前的 FlyBehavior 尝试飞一次，行为通过 try_flight 报告变化量
+//! 飞不起来或者体力耗尽就落到水面上游泳，游泳能慢慢恢复体力
+//! 因为行为是运行时可替换的，模拟过程中 set_fly_behavior 会立刻影响下一个 tick
+
+use crate::strategy::Duck;
+
+pub const MAX_ENERGY: f64 = 10.0;
+const SWIM_SPEED: f64 = 0.5;
+const SWIM_RECOVERY: f64 = 1.0;
+
+/// 某个 tick 结束时一只鸭子的状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuckSnapshot {
+    pub name: String,
+    pub position: f64,
+    pub altitude: f64,
+    pub energy: f64,
+    pub flying: bool,
+}
+
+/// 某个 tick 结束时整个池塘的状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct PondSnapshot {
+    pub tick: u64,
+    pub ducks: Vec<DuckSnapshot>,
+}
+impl PondSnapshot {
+    pub fn duck(&self, name: &str) -> Option<&DuckSnapshot> {
+        self.ducks.iter().find(|d| d.name == name)
+    }
+}
+
+struct PondDuck {
+    duck: Box<dyn Duck>,
+    state: DuckSnapshot,
+}
+impl PondDuck {
+    fn step(&mut self) {
+        let state = &mut self.state;
+        let flight = if state.energy > 0.0 {
+            self.duck.fly_behavior().borrow().try_flight()
+        } else {
+            None
+        };
+        match flight.filter(|m| !m.is_still()) {
+            Some(m) => {
+                state.position += m.distance;
+                state.altitude = (state.altitude + m.altitude).max(0.0);
+                state.energy = (state.energy + m.energy).clamp(0.0, MAX_ENERGY);
+                state.flying = true;
+            }
+            None => {
+                self.duck.swim();
+                state.position += SWIM_SPEED;
+                state.altitude = 0.0;
+                state.energy = (state.energy + SWIM_RECOVERY).min(MAX_ENERGY);
+                state.flying = false;
+            }
+        }
+    }
+}
+
+pub struct Pond {
+    ducks: Vec<PondDuck>,
+    clock: u64,
+}
+
+impl Pond {
+    pub fn new() -> Self {
+        Pond {
+            ducks: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    /// 新加入的鸭子在原点的水面上，体力是满的
+    pub fn add_duck(&mut self, name: &str, duck: Box<dyn Duck>) {
+        self.ducks.push(PondDuck {
+            duck,
+            state: DuckSnapshot {
+                name: name.to_string(),
+                position: 0.0,
+                altitude: 0.0,
+                energy: MAX_ENERGY,
+                flying: false,
+            },
+        });
+    }
+
+    /// 拿到鸭子之后可以在模拟过程中替换它的行为
+    pub fn duck(&self, name: &str) -> Option<&dyn Duck> {
+        self.ducks
+            .iter()
+            .find(|d| d.state.name == name)
+            .map(|d| d.duck.as_ref())
+    }
+
+    pub fn clock(&self) -> u64 {
+        self.clock
+    }
+
+    /// 按加入的顺序推进每只鸭子，返回这个 tick 结束时的状态
+    pub fn tick(&mut self) -> PondSnapshot {
+        self.clock += 1;
+        for d in &mut self.ducks {
+            d.step();
+        }
+        self.snapshot()
+    }
+
+    pub fn run(&mut self, ticks: usize) -> Vec<PondSnapshot> {
+        (0..ticks).map(|_| self.tick()).collect()
+    }
+
+    pub fn snapshot(&self) -> PondSnapshot {
+        PondSnapshot {
+            tick: self.clock,
+            ducks: self.ducks.iter().map(|d| d.state.clone()).collect(),
+        }
+    }
+}
+
+impl Default for Pond {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_pond() {
+    use crate::output;
+    use crate::strategy::{Fallback, FlyNoWay, FlyRocketPowered, FlyWithWings, Limited};
+    use crate::strategy::{MallardDuck, ModelDuck};
+
+    let mut pond = Pond::new();
+    pond.add_duck("mallard", Box::new(MallardDuck::new()));
+    pond.add_duck("model", Box::new(ModelDuck::new()));
+
+    let mut snapshots = Vec::new();
+    let lines = output::capture(|| snapshots = pond.run(3));
+    // mallard 每个 tick 飞一次，model 先说飞不起来再游泳
+    assert_eq!(lines.len(), 9);
+    assert_eq!(snapshots.len(), 3);
+    let last = &snapshots[2];
+    assert_eq!(last.tick, 3);
+    assert_eq!(
+        last.duck("mallard"),
+        Some(&DuckSnapshot {
+            name: "mallard".to_string(),
+            position: 6.0,
+            altitude: 3.0,
+            energy: 7.0,
+            flying: true,
+        })
+    );
+    assert_eq!(
+        last.duck("model"),
+        Some(&DuckSnapshot {
+            name: "model".to_string(),
+            position: 1.5,
+            altitude: 0.0,
+            energy: MAX_ENERGY,
+            flying: false,
+        })
+    );
+
+    pond.duck("model")
+        .unwrap()
+        .set_fly_behavior(Box::new(FlyRocketPowered));
+    pond.duck("mallard")
+        .unwrap()
+        .set_fly_behavior(Box::new(Fallback::new(
+            Limited::new(1, FlyWithWings),
+            FlyNoWay,
+        )));
+    let mut snapshot = pond.snapshot();
+    output::capture(|| snapshot = pond.tick());
+    let model = snapshot.duck("model").unwrap();
+    assert_eq!(
+        (model.position, model.altitude, model.flying),
+        (11.5, 5.0, true)
+    );
+    let mallard = snapshot.duck("mallard").unwrap();
+    assert_eq!((mallard.position, mallard.altitude), (8.0, 4.0));
+
+    // 翅膀累了之后落回水面游泳
+    output::capture(|| snapshot = pond.tick());
+    let mallard = snapshot.duck("mallard").unwrap();
+    assert_eq!(pond.clock(), 5);
+    assert_eq!(
+        (
+            mallard.position,
+            mallard.altitude,
+            mallard.energy,
+            mallard.flying
+        ),
+        (8.5, 0.0, 7.0, false)
+    );
+}
@@ -51,15 +51,19 @@ impl GumballMachine {
         g.borrow_mut().state = state;
         g
     }
-    fn insert_quarter(&self) {
-        self.state.insert_quarter();
+    // 状态会回头 borrow_mut 上下文，所以先把当前状态取出来，放开借用之后再调用
+    fn current_state(machine: &Rc<RefCell<Self>>) -> Rc<dyn State> {
+        machine.borrow().state.clone()
     }
-    fn eject_quarter(&self) {
-        self.state.eject_quarter();
+    fn insert_quarter(machine: &Rc<RefCell<Self>>) {
+        Self::current_state(machine).insert_quarter();
     }
-    fn turn_crank(&self) {
-        self.state.turn_crank();
-        self.state.dispense();
+    fn eject_quarter(machine: &Rc<RefCell<Self>>) {
+        Self::current_state(machine).eject_quarter();
+    }
+    fn turn_crank(machine: &Rc<RefCell<Self>>) {
+        Self::current_state(machine).turn_crank();
+        Self::current_state(machine).dispense();
     }
 
     fn set_state(&mut self, state: Rc<dyn State>) {
@@ -67,7 +71,7 @@ impl GumballMachine {
     }
 
     fn release_ball(&mut self) {
-        println!("A gumball comes rolling out the slot...");
+        outputln!("A gumball comes rolling out the slot...");
         if self.count != 0 {
             self.count -= 1;
         }
@@ -117,7 +121,7 @@ impl std::fmt::Display for NoQuarterState {
 }
 impl State for NoQuarterState {
     fn insert_quarter(&self) {
-        println!("You inserted a quarter");
+        outputln!("You inserted a quarter");
         let gumball_machine = self
             .gumball_machine
             .as_ref()
@@ -128,13 +132,13 @@ impl State for NoQuarterState {
         gumball_machine.borrow_mut().set_state(state);
     }
     fn eject_quarter(&self) {
-        println!("You have't inserted a quarter");
+        outputln!("You have't inserted a quarter");
     }
     fn turn_crank(&self) {
-        println!("You turned, but there's no quarter");
+        outputln!("You turned, but there's no quarter");
     }
     fn dispense(&self) {
-        println!("You need to pay first");
+        outputln!("You need to pay first");
     }
 }
 
@@ -153,22 +157,22 @@ impl std::fmt::Display for HasQuarterState {
 }
 impl State for HasQuarterState {
     fn insert_quarter(&self) {
-        println!("You cant't insert another quarter");
+        outputln!("You cant't insert another quarter");
     }
     fn eject_quarter(&self) {
-        println!("Quarter returned");
+        outputln!("Quarter returned");
         let gumball_machine = self.gumball_machine.as_ref().unwrap().upgrade().unwrap();
         let state = gumball_machine.borrow().no_quarter_state();
         gumball_machine.borrow_mut().set_state(state);
     }
     fn turn_crank(&self) {
-        println!("You turned...");
+        outputln!("You turned...");
         let gumball_machine = self.gumball_machine.as_ref().unwrap().upgrade().unwrap();
         let state = gumball_machine.borrow().sold_state();
         gumball_machine.borrow_mut().set_state(state);
     }
     fn dispense(&self) {
-        println!("No gumball dispensed");
+        outputln!("No gumball dispensed");
     }
 }
 
@@ -187,13 +191,13 @@ impl std::fmt::Display for SoldState {
 }
 impl State for SoldState {
     fn insert_quarter(&self) {
-        println!("Please wait, we're already giving you gumball");
+        outputln!("Please wait, we're already giving you gumball");
     }
     fn eject_quarter(&self) {
-        println!("Sorry, you already turned the crank");
+        outputln!("Sorry, you already turned the crank");
     }
     fn turn_crank(&self) {
-        println!("Turning twice doesn't get you another gumball");
+        outputln!("Turning twice doesn't get you another gumball");
     }
     fn dispense(&self) {
         let gumball_machine = self.gumball_machine.as_ref().unwrap().upgrade().unwrap();
@@ -202,7 +206,7 @@ impl State for SoldState {
             let state = gumball_machine.borrow().no_quarter_state();
             gumball_machine.borrow_mut().set_state(state);
         } else {
-            println!("Oops, out of gumballs!");
+            outputln!("Oops, out of gumballs!");
             let state = gumball_machine.borrow().sold_out_state();
             gumball_machine.borrow_mut().set_state(state);
         }
@@ -224,47 +228,51 @@ impl std::fmt::Display for SoldOutState {
 }
 impl State for SoldOutState {
     fn insert_quarter(&self) {
-        println!("You can't insert quarter, the machine is sold out");
+        outputln!("You can't insert quarter, the machine is sold out");
     }
     fn eject_quarter(&self) {
-        println!("You can't eject, you haven't inserted a quarter yet");
+        outputln!("You can't eject, you haven't inserted a quarter yet");
     }
     fn turn_crank(&self) {
-        println!("You turned, but there are no gumballs");
+        outputln!("You turned, but there are no gumballs");
     }
     fn dispense(&self) {
-        println!("No gumball dispensed");
+        outputln!("No gumball dispensed");
     }
 }
 
 #[test]
 fn test_state() {
     let gumball_machine = GumballMachine::new(5);
-    println!("{}", gumball_machine.borrow());
+    outputln!("{}", gumball_machine.borrow());
 
-    gumball_machine.borrow().insert_quarter();
-    println!("{}", gumball_machine.borrow());
-    // gumball_machine.turn_crank();
-    // println!("{}", gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    outputln!("{}", gumball_machine.borrow());
+    assert_eq!(gumball_machine.borrow().count(), 4);
 
-    // gumball_machine.insert_quarter();
-    // gumball_machine.eject_quarter();
-    // gumball_machine.turn_crank();
-    // println!("{}", gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::eject_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    outputln!("{}", gumball_machine.borrow());
+    assert_eq!(gumball_machine.borrow().count(), 4);
 
-    // gumball_machine.insert_quarter();
-    // gumball_machine.turn_crank();
-    // gumball_machine.insert_quarter();
-    // gumball_machine.turn_crank();
-    // gumball_machine.eject_quarter();
-    // println!("{}", gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    GumballMachine::eject_quarter(&gumball_machine);
+    outputln!("{}", gumball_machine.borrow());
+    assert_eq!(gumball_machine.borrow().count(), 2);
 
-    // gumball_machine.insert_quarter();
-    // gumball_machine.insert_quarter();
-    // gumball_machine.turn_crank();
-    // gumball_machine.insert_quarter();
-    // gumball_machine.turn_crank();
-    // gumball_machine.insert_quarter();
-    // gumball_machine.turn_crank();
-    // println!("{}", gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    GumballMachine::insert_quarter(&gumball_machine);
+    GumballMachine::turn_crank(&gumball_machine);
+    outputln!("{}", gumball_machine.borrow());
+    assert_eq!(gumball_machine.borrow().count(), 0);
+    assert_eq!(gumball_machine.borrow().to_string(), "SoldOutState: 0");
 }
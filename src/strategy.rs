@@ -2,20 +2,123 @@
 //! 它的特征是有一个抽象基类，抽象基类里面的属性是接口，抽象基类方法行为委托给这些接口值的方法
 //! 具体类继承抽象基类，然后传入不同的接口值就能拥有不同的行为，而且有方法能改变里面的接口值
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
 
 pub trait QuackBehavior {
     fn quack(&self);
+    /// 行为的名字，内置行为和 BehaviorRegistry 里的名字一致，方便展示和核对鸭子当前的配置
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+    /// 尝试叫一次，返回是否真的叫了，组合行为靠它决定要不要换下一个行为
+    fn try_quack(&self) -> bool {
+        self.quack();
+        true
+    }
 }
 
 pub trait FlyBehavior {
     fn fly(&self);
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+    /// 尝试飞一次，返回是否真的飞了，组合行为靠它决定要不要换下一个行为
+    fn try_fly(&self) -> bool {
+        self.fly();
+        true
+    }
+    /// 成功飞一次带来的变化量，默认什么都不变
+    fn motion(&self) -> Motion {
+        Motion::default()
+    }
+    /// 尝试飞一次并报告变化量，没飞成返回 None，pond 模拟用它推进鸭子的状态
+    fn try_flight(&self) -> Option<Motion> {
+        if self.try_fly() {
+            Some(self.motion())
+        } else {
+            None
+        }
+    }
+}
+
+/// 一次飞行带来的位移、高度和体力的变化量
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Motion {
+    pub distance: f64,
+    pub altitude: f64,
+    pub energy: f64,
+}
+impl Motion {
+    pub fn new(distance: f64, altitude: f64, energy: f64) -> Self {
+        Motion {
+            distance,
+            altitude,
+            energy,
+        }
+    }
+    /// 没有位移也没有升高，就是没有离开水面
+    pub fn is_still(&self) -> bool {
+        self.distance == 0.0 && self.altitude == 0.0
+    }
+}
+impl std::ops::Add for Motion {
+    type Output = Motion;
+    fn add(self, other: Motion) -> Motion {
+        Motion::new(
+            self.distance + other.distance,
+            self.altitude + other.altitude,
+            self.energy + other.energy,
+        )
+    }
+}
+
+impl<T: QuackBehavior + ?Sized> QuackBehavior for Box<T> {
+    fn quack(&self) {
+        (**self).quack()
+    }
+    fn name(&self) -> String {
+        (**self).name()
+    }
+    fn try_quack(&self) -> bool {
+        (**self).try_quack()
+    }
+}
+
+impl<T: FlyBehavior + ?Sized> FlyBehavior for Box<T> {
+    fn fly(&self) {
+        (**self).fly()
+    }
+    fn name(&self) -> String {
+        (**self).name()
+    }
+    fn try_fly(&self) -> bool {
+        (**self).try_fly()
+    }
+    fn motion(&self) -> Motion {
+        (**self).motion()
+    }
+    fn try_flight(&self) -> Option<Motion> {
+        (**self).try_flight()
+    }
 }
 
 /// Duck 抽象 trait
 pub trait Duck {
     fn display(&self);
 
+    /// 鸭子的种类，持久化之后靠它恢复成同一种鸭子，自定义的鸭子会恢复成 DynamicDuck
+    fn kind(&self) -> DuckKind {
+        DuckKind::Dynamic
+    }
+
     fn quack_behavior(&self) -> Rc<RefCell<Box<QuackBehavior>>>;
     fn perform_quack(&self) {
         self.quack_behavior().borrow().quack()
@@ -26,55 +129,581 @@ pub trait Duck {
         self.fly_behavior().borrow().fly()
     }
 
+    /// 行为变更的记录
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>>;
+
     fn set_fly_behavior(&self, fly_behavior: Box<FlyBehavior>) {
         let s = self.fly_behavior();
-        s.replace(fly_behavior);
+        let new = fly_behavior.name();
+        let old = s.replace(fly_behavior);
+        self.history().borrow_mut().replaced_fly(old, new);
+    }
+
+    fn set_quack_behavior(&self, quack_behavior: Box<dyn QuackBehavior>) {
+        let s = self.quack_behavior();
+        let new = quack_behavior.name();
+        let old = s.replace(quack_behavior);
+        self.history().borrow_mut().replaced_quack(old, new);
+    }
+
+    /// 换回上一个飞行行为，没有可以回退的行为时返回 false
+    fn revert_fly_behavior(&self) -> bool {
+        let previous = self.history().borrow_mut().previous_fly.pop_back();
+        match previous {
+            Some(fly_behavior) => {
+                let new = fly_behavior.name();
+                let old = self.fly_behavior().replace(fly_behavior);
+                self.history()
+                    .borrow_mut()
+                    .record(BehaviorSlot::Fly, old.name(), new, true);
+                true
+            }
+            None => false,
+        }
+    }
+    fn revert_quack_behavior(&self) -> bool {
+        let previous = self.history().borrow_mut().previous_quack.pop_back();
+        match previous {
+            Some(quack_behavior) => {
+                let new = quack_behavior.name();
+                let old = self.quack_behavior().replace(quack_behavior);
+                self.history()
+                    .borrow_mut()
+                    .record(BehaviorSlot::Quack, old.name(), new, true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn fly_behavior_name(&self) -> String {
+        self.fly_behavior().borrow().name()
+    }
+    fn quack_behavior_name(&self) -> String {
+        self.quack_behavior().borrow().name()
+    }
+    /// 当前配置，例如 "fly=wings,quack=quack"
+    /// 两种行为都是注册表里的名字时可以原样交给 BehaviorRegistry::build_duck 重新组装，
+    /// 组合出来的行为名字里带逗号和括号，build_duck 不认识，不能这样往返
+    fn behavior_spec(&self) -> String {
+        format!(
+            "fly={},quack={}",
+            self.fly_behavior_name(),
+            self.quack_behavior_name()
+        )
     }
 
     fn swim(&self) {
-        println!("All ducks float, even decoys!");
+        outputln!("All ducks float, even decoys!");
+    }
+}
+
+/// 线程安全的 Duck，行为放在 Arc<RwLock<..>> 里，可以在一个线程替换行为，另一个线程调用 perform_fly
+/// 没有 kind，也不能通过 DuckConfig 持久化，见 DuckConfig
+pub trait SyncDuck: Send + Sync {
+    fn display(&self);
+
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>;
+    fn perform_quack(&self) {
+        self.quack_behavior().read().unwrap().quack()
+    }
+
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>;
+    fn perform_fly(&self) {
+        self.fly_behavior().read().unwrap().fly()
+    }
+
+    /// 行为变更的记录，和 Duck::history 一样只保留最近的几条
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>>;
+
+    // 先锁记录再写行为，两个线程同时替换时记录的顺序和实际替换的顺序一致
+    fn set_fly_behavior(&self, fly_behavior: Box<dyn FlyBehavior + Send + Sync>) {
+        let history = self.history();
+        let mut history = history.lock().unwrap();
+        let new = fly_behavior.name();
+        let old = std::mem::replace(&mut *self.fly_behavior().write().unwrap(), fly_behavior);
+        history.replaced_fly(old, new);
+    }
+
+    fn set_quack_behavior(&self, quack_behavior: Box<dyn QuackBehavior + Send + Sync>) {
+        let history = self.history();
+        let mut history = history.lock().unwrap();
+        let new = quack_behavior.name();
+        let old = std::mem::replace(&mut *self.quack_behavior().write().unwrap(), quack_behavior);
+        history.replaced_quack(old, new);
+    }
+
+    /// 换回上一个飞行行为，没有可以回退的行为时返回 false
+    fn revert_fly_behavior(&self) -> bool {
+        let history = self.history();
+        let mut history = history.lock().unwrap();
+        match history.previous_fly.pop_back() {
+            Some(fly_behavior) => {
+                let new = fly_behavior.name();
+                let old =
+                    std::mem::replace(&mut *self.fly_behavior().write().unwrap(), fly_behavior);
+                history.record(BehaviorSlot::Fly, old.name(), new, true);
+                true
+            }
+            None => false,
+        }
+    }
+    fn revert_quack_behavior(&self) -> bool {
+        let history = self.history();
+        let mut history = history.lock().unwrap();
+        match history.previous_quack.pop_back() {
+            Some(quack_behavior) => {
+                let new = quack_behavior.name();
+                let old =
+                    std::mem::replace(&mut *self.quack_behavior().write().unwrap(), quack_behavior);
+                history.record(BehaviorSlot::Quack, old.name(), new, true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn fly_behavior_name(&self) -> String {
+        self.fly_behavior().read().unwrap().name()
+    }
+    fn quack_behavior_name(&self) -> String {
+        self.quack_behavior().read().unwrap().name()
+    }
+    fn behavior_spec(&self) -> String {
+        format!(
+            "fly={},quack={}",
+            self.fly_behavior_name(),
+            self.quack_behavior_name()
+        )
+    }
+
+    fn swim(&self) {
+        outputln!("All ducks float, even decoys!");
+    }
+}
+
+/// 静态分发的 Duck，行为的类型在编译期确定，perform_quack/perform_fly 没有 Rc -> RefCell -> Box 的间接调用
+/// 需要在运行时替换行为时，用 into_dynamic 转成 DynamicDuck
+pub struct GenericDuck<F: FlyBehavior, Q: QuackBehavior> {
+    fly_behavior: F,
+    quack_behavior: Q,
+}
+
+impl<F: FlyBehavior, Q: QuackBehavior> GenericDuck<F, Q> {
+    pub fn new(fly_behavior: F, quack_behavior: Q) -> Self {
+        GenericDuck {
+            fly_behavior,
+            quack_behavior,
+        }
+    }
+    pub fn display(&self) {
+        outputln!("I'm a generic duck");
+    }
+    pub fn perform_quack(&self) {
+        self.quack_behavior.quack()
+    }
+    pub fn perform_fly(&self) {
+        self.fly_behavior.fly()
+    }
+    pub fn swim(&self) {
+        outputln!("All ducks float, even decoys!");
+    }
+}
+
+impl<F, Q> GenericDuck<F, Q>
+where
+    F: FlyBehavior + 'static,
+    Q: QuackBehavior + 'static,
+{
+    pub fn into_dynamic(self) -> DynamicDuck {
+        DynamicDuck::new(Box::new(self.fly_behavior), Box::new(self.quack_behavior))
+    }
+}
+
+/// 用闭包实现的行为，一次性的行为不用再单独写一个 struct，闭包里还可以捕获计数器、channel 等状态
+/// 同时实现了 FlyBehavior 和 QuackBehavior
+pub struct FnBehavior<F: Fn()> {
+    name: String,
+    f: F,
+}
+impl<F: Fn()> FnBehavior<F> {
+    pub fn new(f: F) -> Self {
+        FnBehavior::named("fn", f)
+    }
+    pub fn named(name: &str, f: F) -> Self {
+        FnBehavior {
+            name: name.to_string(),
+            f,
+        }
+    }
+}
+impl<F: Fn()> FlyBehavior for FnBehavior<F> {
+    fn fly(&self) {
+        (self.f)()
+    }
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+impl<F: Fn()> QuackBehavior for FnBehavior<F> {
+    fn quack(&self) {
+        (self.f)()
+    }
+    fn name(&self) -> String {
+        self.name.clone()
     }
 }
 
 pub struct FlyWithWings;
 impl FlyBehavior for FlyWithWings {
     fn fly(&self) {
-        println!("I'm flying");
+        outputln!("I'm flying");
+    }
+    fn name(&self) -> String {
+        "wings".to_string()
+    }
+    fn motion(&self) -> Motion {
+        Motion::new(2.0, 1.0, -1.0)
     }
 }
 
 pub struct FlyNoWay;
 impl FlyBehavior for FlyNoWay {
     fn fly(&self) {
-        println!("I can't fly");
+        outputln!("I can't fly");
+    }
+    fn name(&self) -> String {
+        "no-way".to_string()
     }
 }
 
 pub struct FlyRocketPowered;
 impl FlyBehavior for FlyRocketPowered {
     fn fly(&self) {
-        println!("I'm flying with a rocket!");
+        outputln!("I'm flying with a rocket!");
+    }
+    fn name(&self) -> String {
+        "rocket".to_string()
+    }
+    // 火箭不消耗体力
+    fn motion(&self) -> Motion {
+        Motion::new(10.0, 5.0, 0.0)
     }
 }
 
-struct Quack;
+pub struct Quack;
 impl QuackBehavior for Quack {
     fn quack(&self) {
-        println!("Quack");
+        outputln!("Quack");
+    }
+    fn name(&self) -> String {
+        "quack".to_string()
     }
 }
 
-struct MuteQuack;
+pub struct MuteQuack;
 impl QuackBehavior for MuteQuack {
     fn quack(&self) {
-        println!("<< Silence >>");
+        outputln!("<< Silence >>");
+    }
+    fn name(&self) -> String {
+        "mute".to_string()
     }
 }
 
-struct Squeak;
+pub struct Squeak;
 impl QuackBehavior for Squeak {
     fn quack(&self) {
-        println!("Squeak");
+        outputln!("Squeak");
+    }
+    fn name(&self) -> String {
+        "squeak".to_string()
+    }
+}
+
+// 组合行为，用已有的行为拼出更复杂的行为，同时实现了 FlyBehavior 和 QuackBehavior
+// 计数用的是原子类型，这样组合出来的行为也能放进 SyncDuck
+
+/// 依次执行所有行为，只要有一个执行了就算成功
+pub struct Sequence<B> {
+    behaviors: Vec<B>,
+}
+impl<B> Sequence<B> {
+    pub fn new(behaviors: Vec<B>) -> Self {
+        Sequence { behaviors }
+    }
+}
+impl<B: FlyBehavior> FlyBehavior for Sequence<B> {
+    fn fly(&self) {
+        self.try_fly();
+    }
+    fn name(&self) -> String {
+        let names: Vec<String> = self.behaviors.iter().map(|b| b.name()).collect();
+        format!("sequence({})", names.join(", "))
+    }
+    fn try_fly(&self) -> bool {
+        self.try_flight().is_some()
+    }
+    fn try_flight(&self) -> Option<Motion> {
+        let mut total: Option<Motion> = None;
+        for b in &self.behaviors {
+            if let Some(m) = b.try_flight() {
+                total = Some(total.unwrap_or_default() + m);
+            }
+        }
+        total
+    }
+}
+impl<B: QuackBehavior> QuackBehavior for Sequence<B> {
+    fn quack(&self) {
+        self.try_quack();
+    }
+    fn name(&self) -> String {
+        let names: Vec<String> = self.behaviors.iter().map(|b| b.name()).collect();
+        format!("sequence({})", names.join(", "))
+    }
+    fn try_quack(&self) -> bool {
+        let mut done = false;
+        for b in &self.behaviors {
+            done |= b.try_quack();
+        }
+        done
+    }
+}
+
+/// 先尝试 primary，不成功再用 fallback
+pub struct Fallback<P, F> {
+    primary: P,
+    fallback: F,
+}
+impl<P, F> Fallback<P, F> {
+    pub fn new(primary: P, fallback: F) -> Self {
+        Fallback { primary, fallback }
+    }
+}
+impl<P: FlyBehavior, F: FlyBehavior> FlyBehavior for Fallback<P, F> {
+    fn fly(&self) {
+        self.try_fly();
+    }
+    fn name(&self) -> String {
+        format!(
+            "fallback({}, {})",
+            self.primary.name(),
+            self.fallback.name()
+        )
+    }
+    fn try_fly(&self) -> bool {
+        self.try_flight().is_some()
+    }
+    fn try_flight(&self) -> Option<Motion> {
+        self.primary
+            .try_flight()
+            .or_else(|| self.fallback.try_flight())
+    }
+}
+impl<P: QuackBehavior, F: QuackBehavior> QuackBehavior for Fallback<P, F> {
+    fn quack(&self) {
+        self.try_quack();
+    }
+    fn name(&self) -> String {
+        format!(
+            "fallback({}, {})",
+            self.primary.name(),
+            self.fallback.name()
+        )
+    }
+    fn try_quack(&self) -> bool {
+        self.primary.try_quack() || self.fallback.try_quack()
+    }
+}
+
+/// 两个行为轮流执行，从 first 开始
+pub struct Alternating<A, B> {
+    first: A,
+    second: B,
+    second_next: AtomicBool,
+}
+impl<A, B> Alternating<A, B> {
+    pub fn new(first: A, second: B) -> Self {
+        Alternating {
+            first,
+            second,
+            second_next: AtomicBool::new(false),
+        }
+    }
+}
+impl<A: FlyBehavior, B: FlyBehavior> FlyBehavior for Alternating<A, B> {
+    fn fly(&self) {
+        self.try_fly();
+    }
+    fn name(&self) -> String {
+        format!("alternating({}, {})", self.first.name(), self.second.name())
+    }
+    fn try_fly(&self) -> bool {
+        self.try_flight().is_some()
+    }
+    fn try_flight(&self) -> Option<Motion> {
+        if self.second_next.fetch_xor(true, Ordering::SeqCst) {
+            self.second.try_flight()
+        } else {
+            self.first.try_flight()
+        }
+    }
+}
+impl<A: QuackBehavior, B: QuackBehavior> QuackBehavior for Alternating<A, B> {
+    fn quack(&self) {
+        self.try_quack();
+    }
+    fn name(&self) -> String {
+        format!("alternating({}, {})", self.first.name(), self.second.name())
+    }
+    fn try_quack(&self) -> bool {
+        if self.second_next.fetch_xor(true, Ordering::SeqCst) {
+            self.second.try_quack()
+        } else {
+            self.first.try_quack()
+        }
+    }
+}
+
+/// 最多执行 n 次，用完之后什么都不做并报告失败，一般和 Fallback 搭配使用
+pub struct Limited<B> {
+    behavior: B,
+    remaining: AtomicUsize,
+}
+impl<B> Limited<B> {
+    pub fn new(n: usize, behavior: B) -> Self {
+        Limited {
+            behavior,
+            remaining: AtomicUsize::new(n),
+        }
+    }
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::SeqCst)
+    }
+    fn take(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+impl<B: FlyBehavior> FlyBehavior for Limited<B> {
+    fn fly(&self) {
+        self.try_fly();
+    }
+    fn name(&self) -> String {
+        format!("limited({}, {})", self.remaining(), self.behavior.name())
+    }
+    fn try_fly(&self) -> bool {
+        self.try_flight().is_some()
+    }
+    fn try_flight(&self) -> Option<Motion> {
+        if self.take() {
+            self.behavior.try_flight()
+        } else {
+            None
+        }
+    }
+}
+impl<B: QuackBehavior> QuackBehavior for Limited<B> {
+    fn quack(&self) {
+        self.try_quack();
+    }
+    fn name(&self) -> String {
+        format!("limited({}, {})", self.remaining(), self.behavior.name())
+    }
+    fn try_quack(&self) -> bool {
+        self.take() && self.behavior.try_quack()
+    }
+}
+
+/// 每只鸭子默认保留的变更记录条数
+pub const HISTORY_LIMIT: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BehaviorSlot {
+    Fly,
+    Quack,
+}
+
+/// 一次行为变更，reverted 表示这次变更是回退到之前的行为
+#[derive(Debug, Clone, PartialEq)]
+pub struct BehaviorChange {
+    pub seq: u64,
+    pub slot: BehaviorSlot,
+    pub old: String,
+    pub new: String,
+    pub reverted: bool,
+}
+
+/// 行为变更的记录，只保留最近的 limit 条，被替换掉的行为也留着用来回退
+/// F、Q 是留着回退的行为的类型，SyncDuck 的行为要求 Send + Sync，用 SyncBehaviorHistory
+pub struct BehaviorHistory<F: ?Sized = dyn FlyBehavior, Q: ?Sized = dyn QuackBehavior> {
+    limit: usize,
+    next_seq: u64,
+    changes: VecDeque<BehaviorChange>,
+    previous_fly: VecDeque<Box<F>>,
+    previous_quack: VecDeque<Box<Q>>,
+}
+
+/// SyncDuck 用的变更记录，放在 Arc<Mutex<..>> 里在线程之间共享
+pub type SyncBehaviorHistory =
+    BehaviorHistory<dyn FlyBehavior + Send + Sync, dyn QuackBehavior + Send + Sync>;
+
+impl<F: FlyBehavior + ?Sized, Q: QuackBehavior + ?Sized> BehaviorHistory<F, Q> {
+    pub fn new() -> Self {
+        BehaviorHistory::with_limit(HISTORY_LIMIT)
+    }
+    pub fn with_limit(limit: usize) -> Self {
+        BehaviorHistory {
+            limit,
+            next_seq: 1,
+            changes: VecDeque::new(),
+            previous_fly: VecDeque::new(),
+            previous_quack: VecDeque::new(),
+        }
+    }
+
+    /// 从旧到新
+    pub fn changes(&self) -> Vec<BehaviorChange> {
+        self.changes.iter().cloned().collect()
+    }
+    pub fn last_change(&self) -> Option<&BehaviorChange> {
+        self.changes.back()
+    }
+
+    fn record(&mut self, slot: BehaviorSlot, old: String, new: String, reverted: bool) {
+        self.changes.push_back(BehaviorChange {
+            seq: self.next_seq,
+            slot,
+            old,
+            new,
+            reverted,
+        });
+        self.next_seq += 1;
+        if self.changes.len() > self.limit {
+            self.changes.pop_front();
+        }
+    }
+    fn replaced_fly(&mut self, old: Box<F>, new: String) {
+        self.record(BehaviorSlot::Fly, old.name(), new, false);
+        self.previous_fly.push_back(old);
+        if self.previous_fly.len() > self.limit {
+            self.previous_fly.pop_front();
+        }
+    }
+    fn replaced_quack(&mut self, old: Box<Q>, new: String) {
+        self.record(BehaviorSlot::Quack, old.name(), new, false);
+        self.previous_quack.push_back(old);
+        if self.previous_quack.len() > self.limit {
+            self.previous_quack.pop_front();
+        }
+    }
+}
+
+impl<F: FlyBehavior + ?Sized, Q: QuackBehavior + ?Sized> Default for BehaviorHistory<F, Q> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -82,6 +711,7 @@ impl QuackBehavior for Squeak {
 pub struct MallardDuck {
     quack_behavior: Rc<RefCell<Box<QuackBehavior>>>,
     fly_behavior: Rc<RefCell<Box<FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
 }
 
 impl MallardDuck {
@@ -92,13 +722,17 @@ impl MallardDuck {
         MallardDuck {
             fly_behavior: fly.clone(),
             quack_behavior: quack.clone(),
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
         }
     }
 }
 
 impl Duck for MallardDuck {
     fn display(&self) {
-        println!("I'm a real Mallard duck");
+        outputln!("I'm a real Mallard duck");
+    }
+    fn kind(&self) -> DuckKind {
+        DuckKind::Mallard
     }
     fn quack_behavior(&self) -> Rc<RefCell<Box<QuackBehavior>>> {
         self.quack_behavior.clone()
@@ -106,12 +740,16 @@ impl Duck for MallardDuck {
     fn fly_behavior(&self) -> Rc<RefCell<Box<FlyBehavior>>> {
         self.fly_behavior.clone()
     }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
 }
 
 ////////////////
 pub struct ModelDuck {
     quack_behavior: Rc<RefCell<Box<QuackBehavior>>>,
     fly_behavior: Rc<RefCell<Box<FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
 }
 
 impl ModelDuck {
@@ -121,12 +759,16 @@ impl ModelDuck {
         ModelDuck {
             fly_behavior: fly,
             quack_behavior: quack,
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
         }
     }
 }
 impl Duck for ModelDuck {
     fn display(&self) {
-        println!("I'm a Model duck");
+        outputln!("I'm a Model duck");
+    }
+    fn kind(&self) -> DuckKind {
+        DuckKind::Model
     }
     fn quack_behavior(&self) -> Rc<RefCell<Box<QuackBehavior>>> {
         self.quack_behavior.clone()
@@ -134,4 +776,959 @@ impl Duck for ModelDuck {
     fn fly_behavior(&self) -> Rc<RefCell<Box<FlyBehavior>>> {
         self.fly_behavior.clone()
     }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct RedheadDuck {
+    quack_behavior: Rc<RefCell<Box<dyn QuackBehavior>>>,
+    fly_behavior: Rc<RefCell<Box<dyn FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
+}
+
+impl RedheadDuck {
+    pub fn new() -> Self {
+        RedheadDuck {
+            fly_behavior: Rc::new(RefCell::new(Box::new(FlyWithWings {}))),
+            quack_behavior: Rc::new(RefCell::new(Box::new(Quack {}))),
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for RedheadDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Duck for RedheadDuck {
+    fn display(&self) {
+        outputln!("I'm a real Red Headed duck");
+    }
+    fn kind(&self) -> DuckKind {
+        DuckKind::Redhead
+    }
+    fn quack_behavior(&self) -> Rc<RefCell<Box<dyn QuackBehavior>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Rc<RefCell<Box<dyn FlyBehavior>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct RubberDuck {
+    quack_behavior: Rc<RefCell<Box<dyn QuackBehavior>>>,
+    fly_behavior: Rc<RefCell<Box<dyn FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
+}
+
+impl RubberDuck {
+    pub fn new() -> Self {
+        RubberDuck {
+            fly_behavior: Rc::new(RefCell::new(Box::new(FlyNoWay {}))),
+            quack_behavior: Rc::new(RefCell::new(Box::new(Squeak {}))),
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for RubberDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Duck for RubberDuck {
+    fn display(&self) {
+        outputln!("I'm a rubber duckie");
+    }
+    fn kind(&self) -> DuckKind {
+        DuckKind::Rubber
+    }
+    fn quack_behavior(&self) -> Rc<RefCell<Box<dyn QuackBehavior>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Rc<RefCell<Box<dyn FlyBehavior>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct DecoyDuck {
+    quack_behavior: Rc<RefCell<Box<dyn QuackBehavior>>>,
+    fly_behavior: Rc<RefCell<Box<dyn FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
+}
+
+impl DecoyDuck {
+    pub fn new() -> Self {
+        DecoyDuck {
+            fly_behavior: Rc::new(RefCell::new(Box::new(FlyNoWay {}))),
+            quack_behavior: Rc::new(RefCell::new(Box::new(MuteQuack {}))),
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for DecoyDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Duck for DecoyDuck {
+    fn display(&self) {
+        outputln!("I'm a duck Decoy");
+    }
+    fn kind(&self) -> DuckKind {
+        DuckKind::Decoy
+    }
+    fn quack_behavior(&self) -> Rc<RefCell<Box<dyn QuackBehavior>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Rc<RefCell<Box<dyn FlyBehavior>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+/// 行为在运行时传入的 Duck
+pub struct DynamicDuck {
+    quack_behavior: Rc<RefCell<Box<dyn QuackBehavior>>>,
+    fly_behavior: Rc<RefCell<Box<dyn FlyBehavior>>>,
+    history: Rc<RefCell<BehaviorHistory>>,
+}
+
+impl DynamicDuck {
+    pub fn new(fly_behavior: Box<dyn FlyBehavior>, quack_behavior: Box<dyn QuackBehavior>) -> Self {
+        DynamicDuck {
+            fly_behavior: Rc::new(RefCell::new(fly_behavior)),
+            quack_behavior: Rc::new(RefCell::new(quack_behavior)),
+            history: Rc::new(RefCell::new(BehaviorHistory::new())),
+        }
+    }
+}
+
+impl<F, Q> From<GenericDuck<F, Q>> for DynamicDuck
+where
+    F: FlyBehavior + 'static,
+    Q: QuackBehavior + 'static,
+{
+    fn from(duck: GenericDuck<F, Q>) -> Self {
+        duck.into_dynamic()
+    }
+}
+
+impl Duck for DynamicDuck {
+    fn display(&self) {
+        outputln!("I'm a generic duck");
+    }
+    fn quack_behavior(&self) -> Rc<RefCell<Box<dyn QuackBehavior>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Rc<RefCell<Box<dyn FlyBehavior>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Rc<RefCell<BehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct SyncMallardDuck {
+    quack_behavior: Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>,
+    fly_behavior: Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>,
+    history: Arc<Mutex<SyncBehaviorHistory>>,
+}
+
+impl SyncMallardDuck {
+    pub fn new() -> Self {
+        SyncMallardDuck {
+            fly_behavior: Arc::new(RwLock::new(Box::new(FlyWithWings {}))),
+            quack_behavior: Arc::new(RwLock::new(Box::new(Quack {}))),
+            history: Arc::new(Mutex::new(SyncBehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for SyncMallardDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncDuck for SyncMallardDuck {
+    fn display(&self) {
+        outputln!("I'm a real Mallard duck");
+    }
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct SyncModelDuck {
+    quack_behavior: Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>,
+    fly_behavior: Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>,
+    history: Arc<Mutex<SyncBehaviorHistory>>,
+}
+
+impl SyncModelDuck {
+    pub fn new() -> Self {
+        SyncModelDuck {
+            fly_behavior: Arc::new(RwLock::new(Box::new(FlyNoWay {}))),
+            quack_behavior: Arc::new(RwLock::new(Box::new(Quack {}))),
+            history: Arc::new(Mutex::new(SyncBehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for SyncModelDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncDuck for SyncModelDuck {
+    fn display(&self) {
+        outputln!("I'm a Model duck");
+    }
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct SyncRedheadDuck {
+    quack_behavior: Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>,
+    fly_behavior: Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>,
+    history: Arc<Mutex<SyncBehaviorHistory>>,
+}
+
+impl SyncRedheadDuck {
+    pub fn new() -> Self {
+        SyncRedheadDuck {
+            fly_behavior: Arc::new(RwLock::new(Box::new(FlyWithWings {}))),
+            quack_behavior: Arc::new(RwLock::new(Box::new(Quack {}))),
+            history: Arc::new(Mutex::new(SyncBehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for SyncRedheadDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncDuck for SyncRedheadDuck {
+    fn display(&self) {
+        outputln!("I'm a real Red Headed duck");
+    }
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct SyncRubberDuck {
+    quack_behavior: Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>,
+    fly_behavior: Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>,
+    history: Arc<Mutex<SyncBehaviorHistory>>,
+}
+
+impl SyncRubberDuck {
+    pub fn new() -> Self {
+        SyncRubberDuck {
+            fly_behavior: Arc::new(RwLock::new(Box::new(FlyNoWay {}))),
+            quack_behavior: Arc::new(RwLock::new(Box::new(Squeak {}))),
+            history: Arc::new(Mutex::new(SyncBehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for SyncRubberDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncDuck for SyncRubberDuck {
+    fn display(&self) {
+        outputln!("I'm a rubber duckie");
+    }
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+pub struct SyncDecoyDuck {
+    quack_behavior: Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>>,
+    fly_behavior: Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>>,
+    history: Arc<Mutex<SyncBehaviorHistory>>,
+}
+
+impl SyncDecoyDuck {
+    pub fn new() -> Self {
+        SyncDecoyDuck {
+            fly_behavior: Arc::new(RwLock::new(Box::new(FlyNoWay {}))),
+            quack_behavior: Arc::new(RwLock::new(Box::new(MuteQuack {}))),
+            history: Arc::new(Mutex::new(SyncBehaviorHistory::new())),
+        }
+    }
+}
+
+impl Default for SyncDecoyDuck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncDuck for SyncDecoyDuck {
+    fn display(&self) {
+        outputln!("I'm a duck Decoy");
+    }
+    fn quack_behavior(&self) -> Arc<RwLock<Box<dyn QuackBehavior + Send + Sync>>> {
+        self.quack_behavior.clone()
+    }
+    fn fly_behavior(&self) -> Arc<RwLock<Box<dyn FlyBehavior + Send + Sync>>> {
+        self.fly_behavior.clone()
+    }
+    fn history(&self) -> Arc<Mutex<SyncBehaviorHistory>> {
+        self.history.clone()
+    }
+}
+
+////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub enum BehaviorRegistryErr {
+    UnknownFlyBehavior { name: String, known: Vec<String> },
+    UnknownQuackBehavior { name: String, known: Vec<String> },
+    UnknownKey(String),
+    MalformedEntry(String),
+    MissingBehavior(&'static str),
+}
+impl Display for BehaviorRegistryErr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            BehaviorRegistryErr::UnknownFlyBehavior { name, known } => write!(
+                f,
+                "unknown fly behavior `{}`, expected one of: {}",
+                name,
+                known.join(", ")
+            ),
+            BehaviorRegistryErr::UnknownQuackBehavior { name, known } => write!(
+                f,
+                "unknown quack behavior `{}`, expected one of: {}",
+                name,
+                known.join(", ")
+            ),
+            BehaviorRegistryErr::UnknownKey(key) => {
+                write!(f, "unknown key `{}`, expected `fly` or `quack`", key)
+            }
+            BehaviorRegistryErr::MalformedEntry(entry) => {
+                write!(f, "malformed entry `{}`, expected `key=name`", entry)
+            }
+            BehaviorRegistryErr::MissingBehavior(key) => write!(f, "missing `{}` behavior", key),
+        }
+    }
+}
+impl Error for BehaviorRegistryErr {}
+
+type FlyFactory = Box<dyn Fn() -> Box<dyn FlyBehavior>>;
+type QuackFactory = Box<dyn Fn() -> Box<dyn QuackBehavior>>;
+
+/// 行为注册表，把名字映射到行为的工厂函数，用来从配置字符串组装 Duck
+pub struct BehaviorRegistry {
+    fly_behaviors: HashMap<String, FlyFactory>,
+    quack_behaviors: HashMap<String, QuackFactory>,
+}
+
+impl BehaviorRegistry {
+    /// 预先注册了内置的行为
+    pub fn new() -> Self {
+        let mut registry = BehaviorRegistry {
+            fly_behaviors: HashMap::new(),
+            quack_behaviors: HashMap::new(),
+        };
+        registry.register_fly("wings", || Box::new(FlyWithWings));
+        registry.register_fly("no-way", || Box::new(FlyNoWay));
+        registry.register_fly("rocket", || Box::new(FlyRocketPowered));
+        registry.register_quack("quack", || Box::new(Quack));
+        registry.register_quack("mute", || Box::new(MuteQuack));
+        registry.register_quack("squeak", || Box::new(Squeak));
+        registry
+    }
+
+    /// 同名的行为会被替换
+    pub fn register_fly<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn FlyBehavior> + 'static,
+    {
+        self.fly_behaviors
+            .insert(name.to_string(), Box::new(factory));
+    }
+    pub fn register_quack<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn() -> Box<dyn QuackBehavior> + 'static,
+    {
+        self.quack_behaviors
+            .insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn fly_behavior(&self, name: &str) -> Result<Box<dyn FlyBehavior>, BehaviorRegistryErr> {
+        match self.fly_behaviors.get(name) {
+            Some(factory) => Ok(factory()),
+            None => Err(BehaviorRegistryErr::UnknownFlyBehavior {
+                name: name.to_string(),
+                known: sorted_names(&self.fly_behaviors),
+            }),
+        }
+    }
+    pub fn quack_behavior(
+        &self,
+        name: &str,
+    ) -> Result<Box<dyn QuackBehavior>, BehaviorRegistryErr> {
+        match self.quack_behaviors.get(name) {
+            Some(factory) => Ok(factory()),
+            None => Err(BehaviorRegistryErr::UnknownQuackBehavior {
+                name: name.to_string(),
+                known: sorted_names(&self.quack_behaviors),
+            }),
+        }
+    }
+
+    /// 根据 "fly=rocket,quack=squeak" 这样的配置组装 Duck，两种行为都必须给出
+    pub fn build_duck(&self, spec: &str) -> Result<DynamicDuck, BehaviorRegistryErr> {
+        let mut fly_behavior = None;
+        let mut quack_behavior = None;
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut kv = entry.splitn(2, '=');
+            let key = kv.next().unwrap_or("").trim();
+            let name = match kv.next() {
+                Some(name) if !key.is_empty() && !name.trim().is_empty() => name.trim(),
+                _ => return Err(BehaviorRegistryErr::MalformedEntry(entry.to_string())),
+            };
+            match key {
+                "fly" => fly_behavior = Some(self.fly_behavior(name)?),
+                "quack" => quack_behavior = Some(self.quack_behavior(name)?),
+                _ => return Err(BehaviorRegistryErr::UnknownKey(key.to_string())),
+            }
+        }
+        let fly_behavior = fly_behavior.ok_or(BehaviorRegistryErr::MissingBehavior("fly"))?;
+        let quack_behavior = quack_behavior.ok_or(BehaviorRegistryErr::MissingBehavior("quack"))?;
+        Ok(DynamicDuck::new(fly_behavior, quack_behavior))
+    }
+}
+
+impl Default for BehaviorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum DuckKind {
+    Mallard,
+    Model,
+    Redhead,
+    Rubber,
+    Decoy,
+    Dynamic,
+}
+
+/// Duck 当前配置的持久化形式，行为用 BehaviorRegistry 里的名字表示
+/// 打开 serde feature 之后可以存成 JSON、TOML 等格式
+/// SyncDuck 不在这里面：BehaviorRegistry 造出来的行为不是 Send + Sync，没法恢复成线程安全的鸭子
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DuckConfig {
+    pub kind: DuckKind,
+    pub fly: String,
+    pub quack: String,
+}
+
+impl DuckConfig {
+    /// 运行时通过 set_fly_behavior 换过的行为也会记下来
+    pub fn from_duck(duck: &dyn Duck) -> Self {
+        DuckConfig {
+            kind: duck.kind(),
+            fly: duck.fly_behavior_name(),
+            quack: duck.quack_behavior_name(),
+        }
+    }
+
+    /// 按配置重新创建鸭子，行为的名字必须在 registry 里注册过
+    /// 恢复出来的鸭子变更记录是空的，配置里的行为就是它的初始行为
+    pub fn restore(
+        &self,
+        registry: &BehaviorRegistry,
+    ) -> Result<Box<dyn Duck>, BehaviorRegistryErr> {
+        let fly_behavior = registry.fly_behavior(&self.fly)?;
+        let quack_behavior = registry.quack_behavior(&self.quack)?;
+        let duck: Box<dyn Duck> = match self.kind {
+            DuckKind::Mallard => Box::new(MallardDuck::new()),
+            DuckKind::Model => Box::new(ModelDuck::new()),
+            DuckKind::Redhead => Box::new(RedheadDuck::new()),
+            DuckKind::Rubber => Box::new(RubberDuck::new()),
+            DuckKind::Decoy => Box::new(DecoyDuck::new()),
+            DuckKind::Dynamic => {
+                return Ok(Box::new(DynamicDuck::new(fly_behavior, quack_behavior)));
+            }
+        };
+        // 不走 set_fly_behavior，否则新鸭子一开始就有两条变更，还能回退到构造函数给的默认行为
+        duck.fly_behavior().replace(fly_behavior);
+        duck.quack_behavior().replace(quack_behavior);
+        Ok(duck)
+    }
+}
+
+/// 鸭子序列化成它的 DuckConfig，反序列化时先得到 DuckConfig 再 restore
+#[cfg(feature = "serde")]
+impl Serialize for dyn Duck {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DuckConfig::from_duck(self).serialize(serializer)
+    }
+}
+
+fn sorted_names<V>(map: &HashMap<String, V>) -> Vec<String> {
+    let mut names: Vec<String> = map.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_generic_duck() {
+    use crate::output;
+
+    let duck = GenericDuck::new(FlyWithWings, Squeak);
+    let lines = output::capture(|| {
+        duck.perform_quack();
+        duck.perform_fly();
+    });
+    assert_eq!(lines, vec!["Squeak", "I'm flying"]);
+
+    let duck: DynamicDuck = duck.into();
+    duck.set_fly_behavior(Box::new(FlyRocketPowered));
+    let lines = output::capture(|| {
+        duck.perform_quack();
+        duck.perform_fly();
+    });
+    assert_eq!(lines, vec!["Squeak", "I'm flying with a rocket!"]);
+}
+
+#[test]
+fn test_fn_behavior() {
+    use std::cell::Cell;
+    use std::sync::mpsc;
+
+    let model = ModelDuck::new();
+    let flights = Rc::new(Cell::new(0));
+    let counter = flights.clone();
+    model.set_fly_behavior(Box::new(FnBehavior::new(move || {
+        counter.set(counter.get() + 1)
+    })));
+    model.perform_fly();
+    model.perform_fly();
+    assert_eq!(flights.get(), 2);
+
+    let (tx, rx) = mpsc::channel();
+    model.set_quack_behavior(Box::new(FnBehavior::new(move || tx.send("Honk").unwrap())));
+    model.perform_quack();
+    assert_eq!(rx.try_recv(), Ok("Honk"));
+
+    let sync_model = SyncModelDuck::new();
+    let (tx, rx) = mpsc::channel();
+    sync_model.set_quack_behavior(Box::new(FnBehavior::new(move || tx.send("Honk").unwrap())));
+    std::thread::spawn(move || sync_model.perform_quack())
+        .join()
+        .unwrap();
+    assert_eq!(rx.try_recv(), Ok("Honk"));
+}
+
+#[test]
+fn test_behavior_combinators() {
+    use crate::output;
+
+    let model = ModelDuck::new();
+    model.set_fly_behavior(Box::new(Fallback::new(
+        Limited::new(2, FlyWithWings),
+        FlyNoWay,
+    )));
+    model.set_quack_behavior(Box::new(Alternating::new(Quack, Squeak)));
+    let lines = output::capture(|| {
+        for _ in 0..3 {
+            model.perform_fly();
+            model.perform_quack();
+        }
+    });
+    assert_eq!(
+        lines,
+        vec![
+            "I'm flying",
+            "Quack",
+            "I'm flying",
+            "Squeak",
+            "I can't fly",
+            "Quack"
+        ]
+    );
+
+    let sequence: Sequence<Box<dyn FlyBehavior>> =
+        Sequence::new(vec![Box::new(FlyWithWings), Box::new(FlyRocketPowered)]);
+    let lines = output::capture(|| assert!(sequence.try_fly()));
+    assert_eq!(lines, vec!["I'm flying", "I'm flying with a rocket!"]);
+
+    let tired = Limited::new(1, Squeak);
+    assert!(output::capture(|| assert!(tired.try_quack())) == vec!["Squeak"]);
+    assert!(output::capture(|| assert!(!tired.try_quack())).is_empty());
+    assert_eq!(tired.remaining(), 0);
+
+    let sync_model = SyncModelDuck::new();
+    sync_model.set_fly_behavior(Box::new(Fallback::new(
+        Limited::new(1, FlyRocketPowered),
+        FlyNoWay,
+    )));
+    std::thread::spawn(move || sync_model.perform_fly())
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_duck_catalog() {
+    use crate::output;
+
+    let ducks: Vec<(Box<dyn Duck>, &str)> = vec![
+        (Box::new(MallardDuck::new()), "fly=wings,quack=quack"),
+        (Box::new(ModelDuck::new()), "fly=no-way,quack=quack"),
+        (Box::new(RedheadDuck::new()), "fly=wings,quack=quack"),
+        (Box::new(RubberDuck::new()), "fly=no-way,quack=squeak"),
+        (Box::new(DecoyDuck::new()), "fly=no-way,quack=mute"),
+    ];
+    let registry = BehaviorRegistry::new();
+    for (duck, spec) in &ducks {
+        assert_eq!(duck.behavior_spec(), *spec);
+        // 配置能原样交给 BehaviorRegistry 重新组装
+        assert_eq!(registry.build_duck(spec).unwrap().behavior_spec(), *spec);
+    }
+
+    let rubber = RubberDuck::new();
+    let lines = output::capture(|| {
+        rubber.display();
+        rubber.perform_quack();
+        rubber.set_quack_behavior(Box::new(MuteQuack));
+        rubber.perform_quack();
+    });
+    assert_eq!(
+        lines,
+        vec!["I'm a rubber duckie", "Squeak", "<< Silence >>"]
+    );
+    assert_eq!(rubber.quack_behavior_name(), "mute");
+
+    let decoy = DecoyDuck::new();
+    decoy.set_fly_behavior(Box::new(Fallback::new(
+        Limited::new(2, FlyWithWings),
+        FlyNoWay,
+    )));
+    assert_eq!(
+        decoy.fly_behavior_name(),
+        "fallback(limited(2, wings), no-way)"
+    );
+    // 组合行为的配置不能交回 build_duck
+    assert_eq!(
+        decoy.behavior_spec(),
+        "fly=fallback(limited(2, wings), no-way),quack=mute"
+    );
+    assert_eq!(
+        registry.build_duck(&decoy.behavior_spec()).err(),
+        Some(BehaviorRegistryErr::UnknownFlyBehavior {
+            name: "fallback(limited(2".to_string(),
+            known: vec![
+                "no-way".to_string(),
+                "rocket".to_string(),
+                "wings".to_string()
+            ],
+        })
+    );
+    decoy.set_quack_behavior(Box::new(FnBehavior::named("honk", || {})));
+    assert_eq!(decoy.quack_behavior_name(), "honk");
+    // 线程安全的版本和上面的鸭子一一对应
+    let sync_ducks: Vec<(Box<dyn SyncDuck>, &str)> = vec![
+        (Box::new(SyncMallardDuck::new()), "I'm a real Mallard duck"),
+        (Box::new(SyncModelDuck::new()), "I'm a Model duck"),
+        (
+            Box::new(SyncRedheadDuck::new()),
+            "I'm a real Red Headed duck",
+        ),
+        (Box::new(SyncRubberDuck::new()), "I'm a rubber duckie"),
+        (Box::new(SyncDecoyDuck::new()), "I'm a duck Decoy"),
+    ];
+    for ((duck, spec), (sync_duck, display)) in ducks.iter().zip(&sync_ducks) {
+        assert_eq!(sync_duck.behavior_spec(), duck.behavior_spec());
+        assert_eq!(sync_duck.behavior_spec(), *spec);
+        assert_eq!(output::capture(|| sync_duck.display()), vec![*display]);
+    }
+}
+
+#[test]
+fn test_duck_config() {
+    let registry = BehaviorRegistry::new();
+    let model = ModelDuck::new();
+    model.set_fly_behavior(Box::new(FlyRocketPowered));
+    let config = DuckConfig::from_duck(&model);
+    assert_eq!(
+        config,
+        DuckConfig {
+            kind: DuckKind::Model,
+            fly: "rocket".to_string(),
+            quack: "quack".to_string(),
+        }
+    );
+    let restored = config.restore(&registry).unwrap();
+    assert_eq!(restored.kind(), DuckKind::Model);
+    assert_eq!(restored.behavior_spec(), "fly=rocket,quack=quack");
+    // 恢复出来的鸭子没有变更记录，也没有可以回退的行为
+    assert!(restored.history().borrow().changes().is_empty());
+    assert!(!restored.revert_fly_behavior());
+    assert!(!restored.revert_quack_behavior());
+    let mallard = DuckConfig::from_duck(&MallardDuck::new())
+        .restore(&registry)
+        .unwrap();
+    assert!(mallard.history().borrow().changes().is_empty());
+    assert!(!mallard.revert_fly_behavior());
+    assert_eq!(mallard.behavior_spec(), "fly=wings,quack=quack");
+
+    model.set_fly_behavior(Box::new(FnBehavior::named("jet", || {})));
+    let err = DuckConfig::from_duck(&model)
+        .restore(&registry)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "unknown fly behavior `jet`, expected one of: no-way, rocket, wings"
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+    let registry = BehaviorRegistry::new();
+    let ducks: Vec<Box<dyn Duck>> = vec![
+        Box::new(MallardDuck::new()),
+        Box::new(ModelDuck::new()),
+        Box::new(RedheadDuck::new()),
+        Box::new(RubberDuck::new()),
+        Box::new(DecoyDuck::new()),
+        Box::new(registry.build_duck("fly=rocket,quack=squeak").unwrap()),
+    ];
+    ducks[1].set_fly_behavior(Box::new(FlyRocketPowered));
+    ducks[4].set_quack_behavior(Box::new(Quack));
+
+    let json = serde_json::to_string(&ducks).unwrap();
+    let configs: Vec<DuckConfig> = serde_json::from_str(&json).unwrap();
+    assert_eq!(configs.len(), ducks.len());
+    for (duck, config) in ducks.iter().zip(&configs) {
+        let restored = config.restore(&registry).unwrap();
+        assert_eq!(restored.kind(), duck.kind());
+        assert_eq!(restored.behavior_spec(), duck.behavior_spec());
+    }
+
+    // 序列化的格式是稳定的
+    assert_eq!(
+        serde_json::to_string(&DuckConfig::from_duck(&RubberDuck::new())).unwrap(),
+        r#"{"kind":"rubber","fly":"no-way","quack":"squeak"}"#
+    );
+}
+
+#[test]
+fn test_behavior_history() {
+    let mallard = MallardDuck::new();
+    mallard.set_fly_behavior(Box::new(FlyRocketPowered));
+    mallard.set_quack_behavior(Box::new(Squeak));
+    mallard.set_fly_behavior(Box::new(FlyNoWay));
+    assert_eq!(
+        mallard.history().borrow().last_change(),
+        Some(&BehaviorChange {
+            seq: 3,
+            slot: BehaviorSlot::Fly,
+            old: "rocket".to_string(),
+            new: "no-way".to_string(),
+            reverted: false,
+        })
+    );
+
+    assert!(mallard.revert_fly_behavior());
+    assert_eq!(mallard.fly_behavior_name(), "rocket");
+    assert!(mallard.revert_fly_behavior());
+    assert_eq!(mallard.fly_behavior_name(), "wings");
+    assert!(!mallard.revert_fly_behavior());
+    assert!(mallard.revert_quack_behavior());
+    assert_eq!(mallard.quack_behavior_name(), "quack");
+
+    let changes = mallard.history().borrow().changes();
+    let summary: Vec<(u64, &str, &str, bool)> = changes
+        .iter()
+        .map(|c| (c.seq, c.old.as_str(), c.new.as_str(), c.reverted))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, "wings", "rocket", false),
+            (2, "quack", "squeak", false),
+            (3, "rocket", "no-way", false),
+            (4, "no-way", "rocket", true),
+            (5, "rocket", "wings", true),
+            (6, "squeak", "quack", true),
+        ]
+    );
+
+    // 只保留最近的 HISTORY_LIMIT 条
+    let model = ModelDuck::new();
+    for _ in 0..HISTORY_LIMIT + 4 {
+        model.set_fly_behavior(Box::new(FlyWithWings));
+    }
+    let changes = model.history().borrow().changes();
+    assert_eq!(changes.len(), HISTORY_LIMIT);
+    assert_eq!(changes[0].seq, 5);
+    let mut reverts = 0;
+    while model.revert_fly_behavior() {
+        reverts += 1;
+    }
+    assert_eq!(reverts, HISTORY_LIMIT);
+}
+
+#[test]
+fn test_sync_duck() {
+    use crate::output;
+    use std::thread;
+
+    let model = Arc::new(SyncModelDuck::new());
+    let simulation = {
+        let model = model.clone();
+        thread::spawn(move || {
+            output::capture(|| {
+                for _ in 0..100 {
+                    model.perform_fly();
+                }
+            })
+        })
+    };
+    let control = {
+        let model = model.clone();
+        thread::spawn(move || model.set_fly_behavior(Box::new(FlyRocketPowered)))
+    };
+    control.join().unwrap();
+    assert_eq!(simulation.join().unwrap().len(), 100);
+
+    let lines = output::capture(|| {
+        model.perform_quack();
+        model.perform_fly();
+    });
+    assert_eq!(lines, vec!["Quack", "I'm flying with a rocket!"]);
+
+    let lines = output::capture(|| SyncMallardDuck::new().perform_fly());
+    assert_eq!(lines, vec!["I'm flying"]);
+
+    // 另一个线程换的行为也记在同一份记录里，可以回退
+    assert_eq!(
+        model.history().lock().unwrap().last_change(),
+        Some(&BehaviorChange {
+            seq: 1,
+            slot: BehaviorSlot::Fly,
+            old: "no-way".to_string(),
+            new: "rocket".to_string(),
+            reverted: false,
+        })
+    );
+    model.set_quack_behavior(Box::new(Squeak));
+    assert!(model.revert_fly_behavior());
+    assert!(!model.revert_fly_behavior());
+    assert!(model.revert_quack_behavior());
+    assert_eq!(model.behavior_spec(), "fly=no-way,quack=quack");
+    assert_eq!(model.history().lock().unwrap().changes().len(), 4);
+}
+
+#[test]
+fn test_behavior_registry() {
+    use crate::output;
+
+    let mut registry = BehaviorRegistry::new();
+    let duck = registry.build_duck("fly=rocket, quack=squeak").unwrap();
+    let lines = output::capture(|| {
+        duck.perform_fly();
+        duck.perform_quack();
+    });
+    assert_eq!(lines, vec!["I'm flying with a rocket!", "Squeak"]);
+
+    struct Honk;
+    impl QuackBehavior for Honk {
+        fn quack(&self) {
+            outputln!("Honk");
+        }
+    }
+    registry.register_quack("honk", || Box::new(Honk));
+    let duck = registry.build_duck("quack=honk,fly=no-way").unwrap();
+    assert_eq!(output::capture(|| duck.perform_quack()), vec!["Honk"]);
+
+    let err = registry.build_duck("fly=jet,quack=quack").err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "unknown fly behavior `jet`, expected one of: no-way, rocket, wings"
+    );
+    assert_eq!(
+        registry.build_duck("fly=wings,honk=quack").err(),
+        Some(BehaviorRegistryErr::UnknownKey("honk".to_string()))
+    );
+    assert_eq!(
+        registry.build_duck("fly=wings,quack").err(),
+        Some(BehaviorRegistryErr::MalformedEntry("quack".to_string()))
+    );
+    assert_eq!(
+        registry.build_duck("fly=wings").err(),
+        Some(BehaviorRegistryErr::MissingBehavior("quack"))
+    );
 }
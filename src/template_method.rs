@@ -16,10 +16,10 @@ pub trait CaffeineBeverageWithHook {
     fn brew(&mut self);
     fn add_condiments(&mut self);
     fn boil_water(&mut self) {
-        println!("Boiling water");
+        outputln!("Boiling water");
     }
     fn pour_in_cup(&mut self) {
-        println!("Pouring into cup");
+        outputln!("Pouring into cup");
     }
     fn customer_wants_condiments(&mut self) -> bool {
         true
@@ -29,10 +29,10 @@ pub trait CaffeineBeverageWithHook {
 struct CoffeeWithHook;
 impl CaffeineBeverageWithHook for CoffeeWithHook {
     fn brew(&mut self) {
-        println!("Dripping Coffee through filter");
+        outputln!("Dripping Coffee through filter");
     }
     fn add_condiments(&mut self) {
-        println!("Adding Sugar and Milk");
+        outputln!("Adding Sugar and Milk");
     }
 
     fn customer_wants_condiments(&mut self) -> bool {
@@ -47,7 +47,7 @@ impl CaffeineBeverageWithHook for CoffeeWithHook {
 
 impl CoffeeWithHook {
     fn get_user_input(&self) -> String {
-        print!("Would you like milk and sugar with you coffee (y/n)");
+        outputln!("Would you like milk and sugar with you coffee (y/n)");
 
         let mut buffer = String::new();
         match io::stdin().read_line(&mut buffer) {
@@ -66,6 +66,6 @@ impl CoffeeWithHook {
 #[test]
 fn test_coffee() {
     let mut coffee = CoffeeWithHook {};
-    println!("\nMaking coffee...");
+    outputln!("\nMaking coffee...");
     coffee.prepare_recipe();
 }